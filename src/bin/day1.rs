@@ -133,8 +133,8 @@ fn windows_increasing(window_size: usize, items: &[u64]) -> usize {
     let mut retval = 0;
 
     for data in items.windows(window_size + 1) {
-        let first: u64 = (&data[..data.len() - 1]).iter().sum();
-        let second: u64 = (&data[1..]).iter().sum();
+        let first: u64 = data[..data.len() - 1].iter().sum();
+        let second: u64 = data[1..].iter().sum();
 
         if second > first {
             retval += 1;
@@ -1,83 +1,119 @@
+use advent2021::delimiters::{
+    self, CompletionScoring, DelimiterSet, ParseResult, StreamValidator, SyntaxScoring,
+};
+use std::env;
+use std::io;
+use thiserror::Error;
+
 const TEST_DATA: &str = include_str!("../../data/day10t.txt");
 const REAL_DATA: &str = include_str!("../../data/day10a.txt");
 
-#[derive(Debug)]
-enum ParseResult {
-    IncompleteLine(Vec<char>),
-    IllegalCharacter(char),
-    UnexpectedClose(char),
-    Success,
-}
-
-fn flip(c: char) -> char {
-    match c {
-        '(' => ')',
-        '[' => ']',
-        '{' => '}',
-        '<' => '>',
-        _ => c,
-    }
+#[derive(Debug, Error)]
+enum Oopsie {
+    #[error("Bad delimiter configuration: {0}")]
+    BadDelimiters(#[from] delimiters::Oopsie),
+    #[error("Couldn't stream lines: {0}")]
+    Io(#[from] io::Error),
 }
 
-fn parse_line(s: &str) -> ParseResult {
-    let mut open_stack = Vec::new();
+fn process_file(
+    file_data: &str,
+    delimiters: &DelimiterSet,
+    syntax: &SyntaxScoring,
+    completion: &CompletionScoring,
+) -> (u64, u64) {
+    let mut validator = StreamValidator::new(delimiters, syntax, completion);
 
-    for c in s.chars() {
-        match c {
-            '(' | '[' | '{' | '<' => open_stack.push(flip(c)),
-            ')' | ']' | '}' | '>' => match open_stack.pop() {
-                None => return ParseResult::UnexpectedClose(c),
-                Some(expected) if expected == c => {}
-                Some(_) => return ParseResult::UnexpectedClose(c),
-            },
-            _ => return ParseResult::IllegalCharacter(c),
-        }
+    for line in file_data.lines() {
+        validator.push(line);
     }
 
-    if open_stack.is_empty() {
-        ParseResult::Success
-    } else {
-        ParseResult::IncompleteLine(open_stack)
-    }
+    (
+        validator.statistics().syntax_score,
+        validator.median_completion().unwrap_or_default(),
+    )
 }
 
-fn process_file(file_data: &str) -> (u64, u64) {
-    let mut part1_score = 0;
-    let mut part2_scores = Vec::new();
+#[test]
+fn regression() {
+    let delimiters = DelimiterSet::default();
+    let syntax = SyntaxScoring::default();
+    let completion = CompletionScoring::default();
+    assert_eq!(
+        (26397, 288957),
+        process_file(TEST_DATA, &delimiters, &syntax, &completion)
+    );
+    assert_eq!(
+        (271245, 1685293086),
+        process_file(REAL_DATA, &delimiters, &syntax, &completion)
+    );
+}
 
-    for line in file_data.lines() {
-        match parse_line(line) {
-            ParseResult::UnexpectedClose(')') => part1_score += 3,
-            ParseResult::UnexpectedClose(']') => part1_score += 57,
-            ParseResult::UnexpectedClose('}') => part1_score += 1197,
-            ParseResult::UnexpectedClose('>') => part1_score += 25137,
+fn main() -> Result<(), Oopsie> {
+    let delimiters = DelimiterSet::default();
+    let syntax = SyntaxScoring::default();
+    let completion = CompletionScoring::default();
 
-            ParseResult::IncompleteLine(stack) => {
-                let mut new_score = 0;
+    // `day10 -` validates stdin as a stream, one JSON object per line on
+    // stdout, with the summary going to stderr
+    if env::args().nth(1).as_deref() == Some("-") {
+        let mut validator = StreamValidator::new(&delimiters, &syntax, &completion);
+        validator.run(io::stdin().lock(), io::stdout().lock())?;
+        eprintln!(
+            "{:?}, median completion {:?}",
+            validator.statistics(),
+            validator.median_completion()
+        );
+        return Ok(());
+    }
 
-                for c in stack.iter().rev() {
-                    match c {
-                        ')' => new_score = new_score * 5 + 1,
-                        ']' => new_score = new_score * 5 + 2,
-                        '}' => new_score = new_score * 5 + 3,
-                        '>' => new_score = new_score * 5 + 4,
-                        _ => new_score *= 5,
-                    }
-                }
+    println!(
+        "Test result: {:?}",
+        process_file(TEST_DATA, &delimiters, &syntax, &completion)
+    );
+    println!(
+        "Real result: {:?}",
+        process_file(REAL_DATA, &delimiters, &syntax, &completion)
+    );
 
-                part2_scores.push(new_score);
-            }
+    let mut validator = StreamValidator::new(&delimiters, &syntax, &completion);
+    validator.run(TEST_DATA.as_bytes(), io::stdout().lock())?;
+    println!("{:?}", validator.statistics());
 
+    for line in TEST_DATA.lines() {
+        let result = delimiters.parse_line(line);
+        let repair = delimiters.repair(line, &result);
+        match (result, repair) {
+            (
+                ParseResult::UnexpectedClose {
+                    at,
+                    found,
+                    expected,
+                },
+                Some(repair),
+            ) => println!(
+                "{}:{}: found {}, expected {:?}; try {:?}",
+                at.byte, at.column, found, expected, repair
+            ),
+            (_, Some(repair)) => println!("{} -> {}", line, repair.apply(line)),
             _ => {}
         }
     }
 
-    part2_scores.sort_unstable();
-
-    (part1_score, part2_scores[part2_scores.len() / 2])
-}
+    let commented = DelimiterSet::default()
+        .with_pair("/*", "*/")?
+        .with_quote("\"", "\"")?
+        .with_escape('\\')?;
+    let commented_syntax = SyntaxScoring::new(&[(")", 3), ("*/", 10)]).with_illegal(1);
+    let commented_completion = CompletionScoring::new(3, &[(")", 1), ("*/", 2)]);
+    for line in ["(/*\"(\"*/)", "([\"\\\"\"]/*", "(*/)", "(x)"] {
+        let result = commented.parse_line(line);
+        let score = match &result {
+            ParseResult::IncompleteLine(owed) => commented_completion.score(owed),
+            _ => commented_syntax.score(&result),
+        };
+        println!("{}: {:?} (score {})", line, result, score);
+    }
 
-fn main() {
-    println!("Test result: {:?}", process_file(TEST_DATA));
-    println!("Real result: {:?}", process_file(REAL_DATA));
+    Ok(())
 }
@@ -1,107 +1,289 @@
+use advent2021::graph::{Direction, Graph, NodeId};
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Write;
 use thiserror::Error;
 
 #[derive(Debug, Error, PartialEq)]
 enum Oopsie {
-    #[error("Bad line found: {0}")]
-    BadLine(String),
+    #[error("Couldn't parse cave graph: {0}")]
+    BadGraph(#[from] advent2021::graph::Oopsie),
+    #[error("Can't count paths with a visit limit of {1} on cave {0}")]
+    UnsupportedLimit(String, usize),
+    #[error("Too many limited caves to count paths ({0}, max 64)")]
+    TooManyCaves(usize),
+    #[error("Infinitely many paths loop through cave {0}")]
+    InfinitePaths(String),
 }
 
 const TEST1_DATA: &str = include_str!("../../data/day12t1.txt");
 const TEST2_DATA: &str = include_str!("../../data/day12t2.txt");
 const REAL_DATA: &str = include_str!("../../data/day12a.txt");
 
-struct Graph<'a> {
-    edges: HashMap<&'a str, HashSet<&'a str>>,
+#[derive(Clone, Copy)]
+enum Slot {
+    Blocked,
+    Unlimited,
+    Once(u64),
 }
 
-impl<'a> Graph<'a> {
-    fn add_edge(&mut self, from: &'a str, to: &'a str) {
-        match self.edges.get_mut(from) {
-            None => {
-                let mut new_set = HashSet::new();
-                new_set.insert(to);
-                self.edges.insert(from, new_set);
+// a cave, the once-only caves visited so far, and the ones visited twice
+type CountState = (NodeId, u64, u64);
+
+struct PathCounter<'a> {
+    graph: &'a Graph,
+    policy: &'a VisitPolicy,
+    end: Option<NodeId>,
+    slots: Vec<Slot>,
+    memo: HashMap<CountState, Option<u64>>,
+    dead_ends: HashSet<CountState>,
+}
+
+impl<'a> PathCounter<'a> {
+    fn successors(&self, (cave, visited, doubled): CountState) -> Vec<CountState> {
+        let mut result = Vec::new();
+
+        for next in self.graph.neighbors(cave) {
+            match self.slots[next] {
+                Slot::Blocked => {}
+                Slot::Unlimited => result.push((next, visited, doubled)),
+                Slot::Once(bit) if visited & bit == 0 => {
+                    result.push((next, visited | bit, doubled))
+                }
+                Slot::Once(bit)
+                    if doubled & bit == 0
+                        && self.policy.can_double(self.graph.name(next))
+                        && (doubled.count_ones() as usize) < self.policy.double_visits =>
+                {
+                    result.push((next, visited, doubled | bit))
+                }
+                Slot::Once(_) => {}
             }
-            Some(set) => {
-                set.insert(to);
+        }
+
+        result
+    }
+
+    fn reaches_end(&mut self, state: CountState) -> bool {
+        let mut seen = HashSet::new();
+        let mut pending = vec![state];
+
+        while let Some(state) = pending.pop() {
+            if Some(state.0) == self.end {
+                return true;
+            }
+            if !self.dead_ends.contains(&state) && seen.insert(state) {
+                pending.extend(self.successors(state));
             }
         }
+
+        self.dead_ends.extend(seen);
+        false
     }
 
-    fn paths(&self) -> Paths<'_> {
-        Paths {
-            graph: self,
-            state: VecDeque::from([vec!["start"]]),
+    // coming back around to a state that's still being counted means a loop
+    // through big caves; that's only a problem if the loop can get to the end
+    fn count_from(&mut self, state: CountState) -> Result<u64, Oopsie> {
+        if Some(state.0) == self.end {
+            return Ok(1);
+        }
+
+        match self.memo.get(&state).copied() {
+            Some(Some(x)) => return Ok(x),
+            Some(None) if self.reaches_end(state) => {
+                return Err(Oopsie::InfinitePaths(self.graph.name(state.0).to_string()))
+            }
+            Some(None) => return Ok(0),
+            None => {}
         }
+        self.memo.insert(state, None);
+
+        let mut total = 0;
+        for next in self.successors(state) {
+            total += self.count_from(next)?;
+        }
+
+        self.memo.insert(state, Some(total));
+        Ok(total)
     }
 }
 
-struct Paths<'a> {
-    graph: &'a Graph<'a>,
-    state: VecDeque<Vec<&'a str>>,
+fn is_small(cave: &str) -> bool {
+    cave.chars().all(|x| x.is_lowercase())
 }
 
-fn count<T: PartialEq>(items: &[T], item: &T) -> usize {
-    let mut num = 0;
+struct VisitPolicy {
+    start: String,
+    end: String,
+    limits: HashMap<String, usize>,
+    double_visits: usize,
+}
 
-    for x in items.iter() {
-        if x == item {
-            num += 1;
+impl VisitPolicy {
+    fn part1() -> VisitPolicy {
+        VisitPolicy {
+            start: "start".to_string(),
+            end: "end".to_string(),
+            limits: HashMap::new(),
+            double_visits: 0,
         }
     }
 
-    num
-}
+    fn part2() -> VisitPolicy {
+        VisitPolicy::part1().with_double_visits(1)
+    }
 
-fn still_valid(items: &[&str]) -> bool {
-    let mut counts = HashMap::new();
+    fn with_double_visits(mut self, double_visits: usize) -> VisitPolicy {
+        self.double_visits = double_visits;
+        self
+    }
 
-    for item in items.iter() {
-        if item.chars().all(|x| x.is_lowercase()) {
-            if counts.contains_key(item) {
-                counts.insert(item, 2);
-            } else {
-                counts.insert(item, 1);
-            }
+    fn with_endpoints(mut self, start: &str, end: &str) -> VisitPolicy {
+        self.start = start.to_string();
+        self.end = end.to_string();
+        self
+    }
+
+    fn with_limit(mut self, cave: &str, limit: usize) -> VisitPolicy {
+        self.limits.insert(cave.to_string(), limit);
+        self
+    }
+
+    fn paths<'a>(&'a self, graph: &'a Graph) -> Paths<'a> {
+        Paths {
+            graph,
+            policy: self,
+            state: graph
+                .id(&self.start)
+                .map(|start| (vec![start], 0))
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn count_paths(&self, graph: &Graph) -> Result<u64, Oopsie> {
+        let mut slots = Vec::with_capacity(graph.node_count());
+        let mut next_bit = 0;
+
+        for cave in graph.nodes() {
+            let name = graph.name(cave);
+            let slot = match self.limit(name) {
+                None => Slot::Unlimited,
+                Some(0) => Slot::Blocked,
+                Some(1) => {
+                    next_bit += 1;
+                    Slot::Once(1u64.checked_shl(next_bit - 1).unwrap_or(0))
+                }
+                Some(x) => return Err(Oopsie::UnsupportedLimit(name.to_string(), x)),
+            };
+            slots.push(slot);
+        }
+
+        if next_bit > 64 {
+            return Err(Oopsie::TooManyCaves(next_bit as usize));
+        }
+
+        let start = match graph.id(&self.start) {
+            None => return Ok(0),
+            Some(start) => start,
+        };
+        let visited = match slots[start] {
+            Slot::Once(bit) => bit,
+            _ => 0,
+        };
+        let mut counter = PathCounter {
+            graph,
+            policy: self,
+            end: graph.id(&self.end),
+            slots,
+            memo: HashMap::new(),
+            dead_ends: HashSet::new(),
+        };
+
+        counter.count_from((start, visited, 0))
+    }
+
+    fn limit(&self, cave: &str) -> Option<usize> {
+        if let Some(limit) = self.limits.get(cave) {
+            Some(*limit)
+        } else if cave == self.start || cave == self.end || is_small(cave) {
+            Some(1)
+        } else {
+            None
         }
     }
 
-    let mut caught_two = false;
+    fn can_double(&self, cave: &str) -> bool {
+        cave != self.start && cave != self.end && is_small(cave)
+    }
+
+    // returns the number of double visits used after moving to `next`, or
+    // None if the move isn't allowed
+    fn extend(
+        &self,
+        path: &[NodeId],
+        doubles_used: usize,
+        next: NodeId,
+        name: &str,
+    ) -> Option<usize> {
+        let limit = match self.limit(name) {
+            None => return Some(doubles_used),
+            Some(limit) => limit,
+        };
+        let visits = count(path, &next);
 
-    for count in counts.values() {
-        if caught_two && count > &1 {
-            return false;
+        if visits < limit {
+            Some(doubles_used)
+        } else if visits == limit
+            && limit > 0
+            && self.can_double(name)
+            && doubles_used < self.double_visits
+        {
+            Some(doubles_used + 1)
+        } else {
+            None
         }
+    }
+}
 
-        if count > &1 {
-            caught_two = true;
+fn count<T: PartialEq>(items: &[T], item: &T) -> usize {
+    let mut num = 0;
+
+    for x in items.iter() {
+        if x == item {
+            num += 1;
         }
     }
 
-    true
+    num
+}
+
+struct Paths<'a> {
+    graph: &'a Graph,
+    policy: &'a VisitPolicy,
+    state: VecDeque<(Vec<NodeId>, usize)>,
 }
 
 impl<'a> Iterator for Paths<'a> {
     type Item = Vec<&'a str>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some(possible) = self.state.pop_front() {
+        let graph = self.graph;
+
+        while let Some((possible, doubles_used)) = self.state.pop_front() {
             match possible.last() {
                 None => panic!("Internal error; empty path in queue"),
-                Some(&"end") => return Some(possible),
+                Some(v) if graph.name(*v) == self.policy.end => {
+                    return Some(possible.iter().map(|x| graph.name(*x)).collect())
+                }
                 Some(v) => {
-                    for x in self.graph.edges.get(v).unwrap_or(&HashSet::new()) {
-                        if x == &"start" {
-                            continue;
-                        }
-
-                        if !x.chars().all(|x| x.is_lowercase()) || count(&possible, x) < 2 {
+                    for x in graph.neighbors(*v) {
+                        if let Some(doubles_used) =
+                            self.policy
+                                .extend(&possible, doubles_used, x, graph.name(x))
+                        {
                             let mut copy = possible.clone();
                             copy.push(x);
-                            if still_valid(&copy) {
-                                self.state.push_back(copy);
-                            }
+                            self.state.push_back((copy, doubles_used));
                         }
                     }
                 }
@@ -112,34 +294,244 @@ impl<'a> Iterator for Paths<'a> {
     }
 }
 
-impl<'a> TryFrom<&'a str> for Graph<'a> {
-    type Error = Oopsie;
+// DOT strings only know about escaped quotes and backslashes, unlike `{:?}`
+fn dot_quote(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+
+    result.push('"');
+    for c in s.chars() {
+        if c == '"' || c == '\\' {
+            result.push('\\');
+        }
+        result.push(c);
+    }
+    result.push('"');
+
+    result
+}
+
+fn dot(graph: &Graph, policy: &VisitPolicy, path: Option<&[&str]>) -> String {
+    let (keyword, connector) = match graph.direction() {
+        Direction::Directed => ("digraph", "->"),
+        Direction::Undirected => ("graph", "--"),
+    };
+    let edge_key = |from, to| {
+        if graph.direction() == Direction::Undirected && to < from {
+            (to, from)
+        } else {
+            (from, to)
+        }
+    };
+    let mut steps: HashMap<(&str, &str), Vec<usize>> = HashMap::new();
+
+    for (idx, pair) in path.unwrap_or(&[]).windows(2).enumerate() {
+        steps
+            .entry(edge_key(pair[0], pair[1]))
+            .or_default()
+            .push(idx + 1);
+    }
+
+    let mut result = String::new();
+    writeln!(result, "{} caves {{", keyword).unwrap();
 
-    fn try_from(value: &'a str) -> Result<Self, Self::Error> {
-        let mut graph = Graph {
-            edges: HashMap::new(),
+    for cave in graph.nodes().map(|x| graph.name(x)) {
+        let style = if cave == policy.start {
+            "shape=doublecircle, style=filled, fillcolor=palegreen"
+        } else if cave == policy.end {
+            "shape=doublecircle, style=filled, fillcolor=lightcoral"
+        } else if is_small(cave) {
+            "shape=ellipse"
+        } else {
+            "shape=box, style=bold"
         };
+        writeln!(result, "    {} [{}];", dot_quote(cave), style).unwrap();
+    }
 
-        for line in value.lines() {
-            match line.split_once('-') {
-                None => return Err(Oopsie::BadLine(line.to_string())),
-                Some((left, right)) => {
-                    graph.add_edge(left, right);
-                    graph.add_edge(right, left);
-                }
-            }
+    for (from, to, _) in graph.edges() {
+        let (from, to) = (graph.name(from), graph.name(to));
+        write!(
+            result,
+            "    {} {} {}",
+            dot_quote(from),
+            connector,
+            dot_quote(to)
+        )
+        .unwrap();
+        if let Some(order) = steps.get(&edge_key(from, to)) {
+            let labels: Vec<String> = order.iter().map(|x| x.to_string()).collect();
+            write!(
+                result,
+                " [color=red, penwidth=2, label={}]",
+                dot_quote(&labels.join(","))
+            )
+            .unwrap();
         }
+        writeln!(result, ";").unwrap();
+    }
+
+    writeln!(result, "}}").unwrap();
+    result
+}
+
+#[test]
+fn visit_policies() {
+    let test1 = Graph::from_edge_list(TEST1_DATA, Direction::Undirected).unwrap();
+    let test2 = Graph::from_edge_list(TEST2_DATA, Direction::Undirected).unwrap();
+
+    assert_eq!(10, VisitPolicy::part1().paths(&test1).count());
+    assert_eq!(36, VisitPolicy::part2().paths(&test1).count());
+    assert_eq!(19, VisitPolicy::part1().paths(&test2).count());
+    assert_eq!(103, VisitPolicy::part2().paths(&test2).count());
+
+    let reversed = VisitPolicy::part1().with_endpoints("end", "start");
+    assert_eq!(10, reversed.paths(&test1).count());
+    let no_a = VisitPolicy::part1().with_limit("A", 0);
+    assert_eq!(
+        vec![vec!["start", "b", "end"]],
+        no_a.paths(&test1).collect::<Vec<_>>()
+    );
+    let double_b = VisitPolicy::part1().with_limit("b", 2);
+    assert_eq!(
+        VisitPolicy::part2()
+            .paths(&test1)
+            .filter(|path| !path
+                .iter()
+                .any(|x| *x != "b" && is_small(x) && count(path, x) > 1))
+            .count(),
+        double_b.paths(&test1).count()
+    );
+    assert!(
+        VisitPolicy::part1()
+            .with_double_visits(2)
+            .paths(&test2)
+            .count()
+            > VisitPolicy::part2().paths(&test2).count()
+    );
+}
 
-        Ok(graph)
+#[test]
+fn memoized_counts() {
+    let test1 = Graph::from_edge_list(TEST1_DATA, Direction::Undirected).unwrap();
+    let test2 = Graph::from_edge_list(TEST2_DATA, Direction::Undirected).unwrap();
+    let policies = [
+        VisitPolicy::part1(),
+        VisitPolicy::part2(),
+        VisitPolicy::part1().with_double_visits(2),
+        VisitPolicy::part2().with_endpoints("end", "start"),
+        VisitPolicy::part2().with_limit("A", 0),
+        VisitPolicy::part2().with_limit("kj", 0),
+    ];
+
+    for graph in [&test1, &test2] {
+        for policy in policies.iter() {
+            assert_eq!(
+                Ok(policy.paths(graph).count() as u64),
+                policy.count_paths(graph)
+            );
+        }
     }
+
+    assert_eq!(
+        Err(Oopsie::UnsupportedLimit("b".to_string(), 2)),
+        VisitPolicy::part1().with_limit("b", 2).count_paths(&test1)
+    );
+    let looping = Graph::from_edge_list("start-A\nA-B\nB-end", Direction::Undirected).unwrap();
+    assert!(matches!(
+        VisitPolicy::part1().count_paths(&looping),
+        Err(Oopsie::InfinitePaths(_))
+    ));
+    let dead_loop = Graph::from_edge_list(
+        "start-end\nstart-c\nc-A\nA-B\nstart-D\nD-end",
+        Direction::Undirected,
+    )
+    .unwrap();
+    assert_eq!(Ok(2), VisitPolicy::part1().count_paths(&dead_loop));
+    assert_eq!(Ok(2), VisitPolicy::part2().count_paths(&dead_loop));
+    let exit_loop =
+        Graph::from_edge_list("start-A\nA-B\nA-c\nc-end", Direction::Undirected).unwrap();
+    assert_eq!(
+        Err(Oopsie::InfinitePaths("A".to_string())),
+        VisitPolicy::part1().count_paths(&exit_loop)
+    );
 }
 
-fn main() {
-    let test1 = Graph::try_from(TEST1_DATA).unwrap();
-    let test2 = Graph::try_from(TEST2_DATA).unwrap();
-    let real = Graph::try_from(REAL_DATA).unwrap();
+#[test]
+fn dot_output() {
+    let test1 = Graph::from_edge_list(TEST1_DATA, Direction::Undirected).unwrap();
+    let policy = VisitPolicy::part2();
+    let plain = dot(&test1, &policy, None);
+
+    assert!(plain.starts_with("graph caves {\n"));
+    assert!(plain.ends_with("}\n"));
+    assert!(
+        plain.contains("    \"start\" [shape=doublecircle, style=filled, fillcolor=palegreen];\n")
+    );
+    assert!(
+        plain.contains("    \"end\" [shape=doublecircle, style=filled, fillcolor=lightcoral];\n")
+    );
+    assert!(plain.contains("    \"A\" [shape=box, style=bold];\n"));
+    assert!(plain.contains("    \"c\" [shape=ellipse];\n"));
+    assert!(plain.contains("    \"start\" -- \"A\";\n"));
+    assert_eq!(7, plain.matches(" -- ").count());
+    assert!(!plain.contains("label"));
+
+    let path = ["start", "A", "c", "A", "b", "A", "end"];
+    let overlaid = dot(&test1, &policy, Some(&path));
+    assert!(overlaid.contains("    \"start\" -- \"A\" [color=red, penwidth=2, label=\"1\"];\n"));
+    assert!(overlaid.contains("    \"A\" -- \"c\" [color=red, penwidth=2, label=\"2,3\"];\n"));
+    assert!(overlaid.contains("[color=red, penwidth=2, label=\"4,5\"];\n"));
+    assert_eq!(4, overlaid.matches("color=red").count());
+
+    let directed = Graph::from_edge_list(TEST1_DATA, Direction::Directed).unwrap();
+    let output = dot(&directed, &policy, Some(&["start", "A", "end"]));
+    assert!(output.starts_with("digraph caves {\n"));
+    assert!(output.contains("    \"A\" -> \"end\" [color=red, penwidth=2, label=\"2\"];\n"));
+
+    let odd = Graph::from_edge_list(
+        "start-café\ncafé-O'B\\\"x\nO'B\\\"x-end",
+        Direction::Undirected,
+    )
+    .unwrap();
+    let output = dot(&odd, &policy, None);
+    assert!(output.contains("    \"café\" [shape=ellipse];\n"));
+    assert!(output.contains("    \"café\" -- \"O'B\\\\\\\"x\";\n"));
+    assert_eq!("\"a\\\\b\\\"c\"", dot_quote("a\\b\"c"));
+}
+
+fn main() -> Result<(), Oopsie> {
+    let test1 = Graph::from_edge_list(TEST1_DATA, Direction::Undirected)?;
+    let test2 = Graph::from_edge_list(TEST2_DATA, Direction::Undirected)?;
+    let real = Graph::from_edge_list(REAL_DATA, Direction::Undirected)?;
+
+    for (name, policy) in [
+        ("Part 1", VisitPolicy::part1()),
+        ("Part 2", VisitPolicy::part2()),
+    ] {
+        println!("{} test #1 count: {}", name, policy.paths(&test1).count());
+        println!("{} test #2 count: {}", name, policy.paths(&test2).count());
+        println!("{} real count: {}", name, policy.paths(&real).count());
+        println!(
+            "{} real count (memoized): {}",
+            name,
+            policy.count_paths(&real)?
+        );
+    }
+
+    let double_b = VisitPolicy::part1().with_limit("b", 2);
+    println!(
+        "Test #1 count with b visited up to twice: {}",
+        double_b.paths(&test1).count()
+    );
+    let reversed = VisitPolicy::part1().with_endpoints("end", "start");
+    println!(
+        "Test #1 count from end to start: {}",
+        reversed.paths(&test1).count()
+    );
+
+    let policy = VisitPolicy::part1();
+    let longest = policy.paths(&test1).max_by_key(|x| x.len());
+    println!("Test #1 graph, with its longest path:");
+    print!("{}", dot(&test1, &policy, longest.as_deref()));
 
-    println!("Test #1 count: {}", test1.paths().count());
-    println!("Test #2 count: {}", test2.paths().count());
-    println!("Real count: {}", real.paths().count());
+    Ok(())
 }
@@ -1,3 +1,5 @@
+use advent2021::map::Graph;
+use core::fmt;
 use core::num::ParseIntError;
 use std::str::FromStr;
 use thiserror::Error;
@@ -13,9 +15,11 @@ enum Oopsie {
     BadFold(String),
     #[error("Couldn't parse number: {0}")]
     ParseIntError(#[from] ParseIntError),
+    #[error("Dot at {0} sits right on the fold line")]
+    DotOnFoldLine(Point),
 }
 
-#[derive(Debug, PartialEq, PartialOrd, Eq, Ord)]
+#[derive(Clone, Debug, PartialEq, PartialOrd, Eq, Ord)]
 struct Point {
     x: usize,
     y: usize,
@@ -36,29 +40,60 @@ impl FromStr for Point {
     }
 }
 
-enum Fold {
-    AlongX(usize),
-    AlongY(usize),
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{},{}", self.x, self.y)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Axis {
+    X,
+    Y,
+}
+
+// which end of the axis the folded paper ends up on
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Side {
+    Low,
+    High,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Fold {
+    axis: Axis,
+    line: usize,
+    toward: Side,
 }
 
 impl FromStr for Fold {
     type Err = Oopsie;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.strip_prefix("fold along ") {
-            None => Err(Oopsie::BadFold(s.to_string())),
-            Some(line) => {
-                if let Some(num) = line.strip_prefix("x=") {
-                    return Ok(Fold::AlongX(usize::from_str(num)?));
-                }
-
-                if let Some(num) = line.strip_prefix("y=") {
-                    return Ok(Fold::AlongY(usize::from_str(num)?));
-                }
-
-                Err(Oopsie::BadFold(s.to_string()))
-            }
-        }
+        let bad = || Oopsie::BadFold(s.to_string());
+        let line = s.strip_prefix("fold along ").ok_or_else(bad)?;
+        let (line, direction) = match line.split_once(' ') {
+            None => (line, None),
+            Some((line, direction)) => (line, Some(direction)),
+        };
+        let (axis, num) = if let Some(num) = line.strip_prefix("x=") {
+            (Axis::X, num)
+        } else if let Some(num) = line.strip_prefix("y=") {
+            (Axis::Y, num)
+        } else {
+            return Err(bad());
+        };
+        let toward = match (axis, direction) {
+            (_, None) | (Axis::X, Some("left")) | (Axis::Y, Some("up")) => Side::Low,
+            (Axis::X, Some("right")) | (Axis::Y, Some("down")) => Side::High,
+            _ => return Err(bad()),
+        };
+
+        Ok(Fold {
+            axis,
+            line: usize::from_str(num)?,
+            toward,
+        })
     }
 }
 
@@ -83,59 +118,174 @@ fn parse_file(contents: &str) -> Result<(Vec<Point>, Vec<Fold>), Oopsie> {
     Ok((points, folds))
 }
 
-impl Point {
-    fn fold(&mut self, fold: &Fold) {
-        match fold {
-            Fold::AlongY(value) => {
-                if &self.y > value {
-                    let difference = self.y - value;
-                    self.y = value - difference;
-                }
-            }
-            Fold::AlongX(value) => {
-                if &self.x > value {
-                    let difference = self.x - value;
-                    self.x = value - difference;
-                }
+struct Paper {
+    points: Vec<Point>,
+    width: usize,
+    height: usize,
+    history: Vec<(Fold, Vec<Point>, usize, usize)>,
+}
+
+impl Paper {
+    fn new(mut points: Vec<Point>) -> Paper {
+        let width = points.iter().map(|p| p.x + 1).max().unwrap_or(0);
+        let height = points.iter().map(|p| p.y + 1).max().unwrap_or(0);
+
+        points.sort_unstable();
+        points.dedup();
+        Paper {
+            points,
+            width,
+            height,
+            history: Vec::new(),
+        }
+    }
+
+    fn fold(&mut self, fold: &Fold) -> Result<Graph<bool>, Oopsie> {
+        if let Some(point) = self.points.iter().find(|p| match fold.axis {
+            Axis::X => p.x == fold.line,
+            Axis::Y => p.y == fold.line,
+        }) {
+            return Err(Oopsie::DotOnFoldLine(point.clone()));
+        }
+
+        let size = match fold.axis {
+            Axis::X => self.width,
+            Axis::Y => self.height,
+        };
+        // the two flaps don't have to be the same size; the result is as
+        // big as the larger one, and the smaller one lines up at the crease
+        let folded = fold.line.max(size.saturating_sub(fold.line + 1));
+        let mut points = self.points.clone();
+
+        for point in points.iter_mut() {
+            let coord = match fold.axis {
+                Axis::X => &mut point.x,
+                Axis::Y => &mut point.y,
+            };
+            let distance = coord.abs_diff(fold.line);
+            *coord = match fold.toward {
+                Side::Low => folded - distance,
+                Side::High => distance - 1,
+            };
+        }
+        points.sort_unstable();
+        points.dedup();
+
+        let previous = std::mem::replace(&mut self.points, points);
+        self.history
+            .push((*fold, previous, self.width, self.height));
+        match fold.axis {
+            Axis::X => self.width = folded,
+            Axis::Y => self.height = folded,
+        }
+
+        Ok(self.grid())
+    }
+
+    fn undo(&mut self) -> Option<Fold> {
+        let (fold, points, width, height) = self.history.pop()?;
+
+        self.points = points;
+        self.width = width;
+        self.height = height;
+        Some(fold)
+    }
+
+    fn grid(&self) -> Graph<bool> {
+        let mut grid = Graph::new(self.width, self.height, false);
+
+        for point in self.points.iter() {
+            if let Some(cell) = grid.get_mut(point.x, point.y) {
+                *cell.value = true;
             }
         }
+
+        grid
     }
 }
 
-fn fold(vec: &mut Vec<Point>, fold: &Fold) {
-    for point in vec.iter_mut() {
-        point.fold(fold);
+fn render(grid: &Graph<bool>) -> String {
+    let mut result = String::with_capacity((grid.width + 1) * grid.height);
+
+    for y in 0..grid.height {
+        for x in 0..grid.width {
+            if *grid.get(x, y).unwrap().value {
+                result.push('#');
+            } else {
+                result.push('.');
+            }
+        }
+        result.push('\n');
     }
 
-    vec.sort_unstable();
-    vec.dedup();
+    result
+}
+
+#[test]
+fn folding() {
+    let (points, folds) = parse_file(TEST_DATA).unwrap();
+    let mut paper = Paper::new(points);
+    paper.fold(&folds[0]).unwrap();
+    assert_eq!(17, paper.points.len());
+    let grid = paper.fold(&folds[1]).unwrap();
+    assert_eq!(16, paper.points.len());
+    assert_eq!(
+        "#####\n#...#\n#...#\n#...#\n#####\n.....\n.....\n",
+        render(&grid)
+    );
+    assert_eq!(Some(folds[1]), paper.undo());
+    assert_eq!(17, paper.points.len());
+    assert_eq!(Some(folds[0]), paper.undo());
+    assert_eq!(18, paper.points.len());
+    assert_eq!(None, paper.undo());
+
+    // the far flap is longer than the near one, which used to underflow
+    let points = vec![
+        Point { x: 0, y: 0 },
+        Point { x: 5, y: 0 },
+        Point { x: 6, y: 0 },
+    ];
+    let mut paper = Paper::new(points.clone());
+    let fold = Fold::from_str("fold along x=1").unwrap();
+    assert_eq!("##..#\n", render(&paper.fold(&fold).unwrap()));
+    let mut paper = Paper::new(points);
+    let fold = Fold::from_str("fold along x=1 right").unwrap();
+    assert_eq!("#..##\n", render(&paper.fold(&fold).unwrap()));
+
+    let mut paper = Paper::new(vec![Point { x: 1, y: 1 }]);
+    let fold = Fold::from_str("fold along y=1 down").unwrap();
+    assert_eq!(
+        Err(Oopsie::DotOnFoldLine(Point { x: 1, y: 1 })),
+        paper.fold(&fold).map(|_| ())
+    );
+    assert!(Fold::from_str("fold along x=1 up").is_err());
 }
 
 fn main() -> Result<(), Oopsie> {
-    let (mut test_points, test_folds) = parse_file(TEST_DATA)?;
-
-    fold(&mut test_points, &test_folds[0]);
-    println!("{} test points: {:?}", test_points.len(), test_points);
-    fold(&mut test_points, &test_folds[1]);
-    println!("{} test points: {:?}", test_points.len(), test_points);
-    let (mut real_points, real_folds) = parse_file(REAL_DATA)?;
-    fold(&mut real_points, &real_folds[0]);
-    println!("{} real points after first fold", real_points.len());
-    for instr in real_folds[1..].iter() {
-        fold(&mut real_points, instr);
+    let (test_points, test_folds) = parse_file(TEST_DATA)?;
+    let mut test_paper = Paper::new(test_points);
+
+    for instr in test_folds.iter() {
+        let grid = test_paper.fold(instr)?;
+        println!("{} test points:", test_paper.points.len());
+        print!("{}", render(&grid));
     }
-    let max_x = real_points.iter().map(|x| x.x).max().unwrap();
-    let max_y = real_points.iter().map(|x| x.y).max().unwrap();
-    for y in 0..=max_y {
-        for x in 0..=max_x {
-            if real_points.contains(&Point { x, y }) {
-                print!("#")
-            } else {
-                print!(" ")
-            }
-        }
-        println!()
+    while let Some(instr) = test_paper.undo() {
+        println!(
+            "undid {:?}, back to {} points",
+            instr,
+            test_paper.points.len()
+        );
+    }
+
+    let (real_points, real_folds) = parse_file(REAL_DATA)?;
+    let mut real_paper = Paper::new(real_points);
+    let mut grid = real_paper.fold(&real_folds[0])?;
+    println!("{} real points after first fold", real_paper.points.len());
+    for instr in real_folds[1..].iter() {
+        grid = real_paper.fold(instr)?;
     }
+    print!("{}", render(&grid));
 
     Ok(())
 }
@@ -1,4 +1,6 @@
-use std::collections::HashMap;
+use core::fmt;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::num::NonZeroU64;
 use thiserror::Error;
 
 const TEST_DATA: &str = include_str!("../../data/day14t.txt");
@@ -10,6 +12,12 @@ enum Oopsie {
     BadTransform(String),
     #[error("No data found?!")]
     NoData,
+    #[error("Element counts don't fit in a u128")]
+    Overflow,
+    #[error("Polymer would grow past the {0} element limit")]
+    TooLong(usize),
+    #[error("Can't count modulo zero")]
+    ZeroModulus,
 }
 
 struct Transform {
@@ -22,13 +30,24 @@ impl TryFrom<&str> for Transform {
     type Error = Oopsie;
 
     fn try_from(s: &str) -> Result<Self, Self::Error> {
-        match s.split_once(" -> ") {
-            None => Err(Oopsie::BadTransform(s.to_string())),
-            Some((from, to_str)) => Ok(Transform {
-                lead: from.chars().next().unwrap(),
-                follow: from.chars().nth(1).unwrap(),
-                inject: to_str.chars().next().unwrap(),
+        let bad = || Oopsie::BadTransform(s.to_string());
+        let (from, to_str) = s.split_once(" -> ").ok_or_else(bad)?;
+        let mut from = from.chars();
+        let mut to_str = to_str.chars();
+
+        match (
+            from.next(),
+            from.next(),
+            from.next(),
+            to_str.next(),
+            to_str.next(),
+        ) {
+            (Some(lead), Some(follow), None, Some(inject), None) => Ok(Transform {
+                lead,
+                follow,
+                inject,
             }),
+            _ => Err(bad()),
         }
     }
 }
@@ -72,6 +91,127 @@ fn build_transform_dictionary(mut transforms: Vec<Transform>) -> TransformDictio
     result
 }
 
+#[derive(Debug, PartialEq)]
+enum Diagnostic {
+    DuplicateRule(Pair, char),
+    ConflictingRules(Pair, Vec<char>),
+    UncoveredPair(Pair),
+    InertReachablePair(Pair),
+    UnreachableRule(Pair),
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Diagnostic::DuplicateRule((a, b), c) => {
+                write!(f, "rule {}{} -> {} appears more than once", a, b, c)
+            }
+            Diagnostic::ConflictingRules((a, b), cs) => {
+                write!(f, "pair {}{} has conflicting insertions {:?}", a, b, cs)
+            }
+            Diagnostic::UncoveredPair((a, b)) => write!(f, "pair {}{} has no rule", a, b),
+            Diagnostic::InertReachablePair((a, b)) => {
+                write!(f, "pair {}{} can appear but has no rule", a, b)
+            }
+            Diagnostic::UnreachableRule((a, b)) => {
+                write!(f, "rule for {}{} can never apply", a, b)
+            }
+        }
+    }
+}
+
+struct RuleReport {
+    alphabet: Vec<char>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl RuleReport {
+    fn is_clean(&self) -> bool {
+        self.diagnostics.iter().all(|x| {
+            matches!(
+                x,
+                Diagnostic::UncoveredPair(_) | Diagnostic::UnreachableRule(_)
+            )
+        })
+    }
+}
+
+impl fmt::Display for RuleReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alphabet: String = self.alphabet.iter().collect();
+        write!(
+            f,
+            "alphabet {}, {} diagnostic(s)",
+            alphabet,
+            self.diagnostics.len()
+        )?;
+        for diagnostic in self.diagnostics.iter() {
+            write!(f, "\n  {}", diagnostic)?;
+        }
+        Ok(())
+    }
+}
+
+fn validate_rules(template: &str, transforms: &[Transform]) -> RuleReport {
+    let mut rules: BTreeMap<Pair, Vec<char>> = BTreeMap::new();
+    let mut alphabet: BTreeSet<char> = template.chars().collect();
+    let mut diagnostics = Vec::new();
+
+    for transform in transforms.iter() {
+        let pair = (transform.lead, transform.follow);
+        let inserts = rules.entry(pair).or_default();
+        if inserts.contains(&transform.inject) {
+            diagnostics.push(Diagnostic::DuplicateRule(pair, transform.inject));
+        } else {
+            inserts.push(transform.inject);
+        }
+        alphabet.extend([transform.lead, transform.follow, transform.inject]);
+    }
+
+    for (pair, inserts) in rules.iter() {
+        if inserts.len() > 1 {
+            diagnostics.push(Diagnostic::ConflictingRules(*pair, inserts.clone()));
+        }
+    }
+
+    for a in alphabet.iter() {
+        for b in alphabet.iter() {
+            if !rules.contains_key(&(*a, *b)) {
+                diagnostics.push(Diagnostic::UncoveredPair((*a, *b)));
+            }
+        }
+    }
+
+    let chars: Vec<char> = template.chars().collect();
+    let mut pending: Vec<Pair> = chars.windows(2).map(|x| (x[0], x[1])).collect();
+    let mut reachable = BTreeSet::new();
+    while let Some(pair) = pending.pop() {
+        if reachable.insert(pair) {
+            for inject in rules.get(&pair).into_iter().flatten() {
+                pending.push((pair.0, *inject));
+                pending.push((*inject, pair.1));
+            }
+        }
+    }
+
+    for pair in reachable.iter() {
+        if !rules.contains_key(pair) {
+            diagnostics.push(Diagnostic::InertReachablePair(*pair));
+        }
+    }
+
+    for pair in rules.keys() {
+        if !reachable.contains(pair) {
+            diagnostics.push(Diagnostic::UnreachableRule(*pair));
+        }
+    }
+
+    RuleReport {
+        alphabet: alphabet.into_iter().collect(),
+        diagnostics,
+    }
+}
+
 type State = HashMap<Pair, usize>;
 
 macro_rules! insert_update {
@@ -115,40 +255,585 @@ fn step(base: &State, transforms: &TransformDictionary) -> State {
     result
 }
 
-fn steps(base: &State, steps: usize, transforms: &TransformDictionary) -> State {
-    let mut result = base.clone();
+struct Polymer {
+    pairs: State,
+    last: char,
+}
+
+impl Polymer {
+    fn new(template: &str) -> Result<Polymer, Oopsie> {
+        let last = template.chars().last().ok_or(Oopsie::NoData)?;
 
-    for i in 0..steps {
-        println!("At step {}, length is {}", i, result.len());
-        result = step(&result, transforms);
+        Ok(Polymer {
+            pairs: build_initial_state(template),
+            last,
+        })
     }
 
-    result
+    fn step(&self, transforms: &TransformDictionary) -> Polymer {
+        Polymer {
+            pairs: step(&self.pairs, transforms),
+            last: self.last,
+        }
+    }
+
+    fn steps(&self, count: usize, transforms: &TransformDictionary) -> Polymer {
+        let mut result = Polymer {
+            pairs: self.pairs.clone(),
+            last: self.last,
+        };
+
+        for _ in 0..count {
+            result = result.step(transforms);
+        }
+
+        result
+    }
+
+    fn len(&self) -> usize {
+        self.pairs.values().sum::<usize>() + 1
+    }
+
+    // every element except the last one leads exactly one pair
+    fn histogram(&self) -> HashMap<char, usize> {
+        let mut char_counts = HashMap::new();
+
+        for ((c1, _), count) in self.pairs.iter() {
+            insert_update!(char_counts, *c1, *count, |v| *v + count);
+        }
+        insert_update!(char_counts, self.last, 1, |v| *v + 1);
+
+        char_counts
+    }
+
+    fn most_common(&self) -> (usize, Vec<char>) {
+        extreme(&self.histogram(), |count, best| count > best)
+    }
+
+    fn least_common(&self) -> (usize, Vec<char>) {
+        extreme(&self.histogram(), |count, best| count < best)
+    }
+
+    fn score(&self) -> usize {
+        self.most_common().0 - self.least_common().0
+    }
+}
+
+fn extreme<T: Copy + Default + PartialEq>(
+    histogram: &HashMap<char, T>,
+    better: fn(T, T) -> bool,
+) -> (T, Vec<char>) {
+    let mut best = None;
+    let mut elements = Vec::new();
+
+    for (element, count) in histogram.iter() {
+        match best {
+            Some(x) if x == *count => elements.push(*element),
+            Some(x) if !better(*count, x) => {}
+            _ => {
+                best = Some(*count);
+                elements = vec![*element];
+            }
+        }
+    }
+
+    elements.sort_unstable();
+    (best.unwrap_or_default(), elements)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Arithmetic {
+    Checked,
+    Modulo(NonZeroU64),
 }
 
-fn counts(polymer: &State) -> HashMap<char, usize> {
-    let mut char_counts = HashMap::new();
+impl Arithmetic {
+    fn modulo(modulus: u64) -> Result<Arithmetic, Oopsie> {
+        NonZeroU64::new(modulus)
+            .map(Arithmetic::Modulo)
+            .ok_or(Oopsie::ZeroModulus)
+    }
+
+    fn reduce(&self, value: u128) -> u128 {
+        match self {
+            Arithmetic::Checked => value,
+            Arithmetic::Modulo(m) => value % (m.get() as u128),
+        }
+    }
+
+    // in modular mode both operands are already reduced below a u64
+    // modulus, so neither operation can overflow
+    fn add(&self, a: u128, b: u128) -> Result<u128, Oopsie> {
+        match self {
+            Arithmetic::Checked => a.checked_add(b).ok_or(Oopsie::Overflow),
+            Arithmetic::Modulo(_) => Ok(self.reduce(a + b)),
+        }
+    }
 
-    for ((c1, c2), count) in polymer.iter() {
-        insert_update!(char_counts, *c1, *count, |v| *v + count);
-        insert_update!(char_counts, *c2, *count, |v| *v + count);
+    fn mul(&self, a: u128, b: u128) -> Result<u128, Oopsie> {
+        match self {
+            Arithmetic::Checked => a.checked_mul(b).ok_or(Oopsie::Overflow),
+            Arithmetic::Modulo(_) => Ok(self.reduce(a * b)),
+        }
     }
+}
 
-    char_counts
+#[derive(Clone)]
+struct TransitionMatrix {
+    size: usize,
+    cells: Vec<u128>,
 }
 
-fn score(map: &HashMap<char, usize>) -> usize {
-    let high = map.values().max().unwrap();
-    let low = map.values().min().unwrap();
+impl TransitionMatrix {
+    fn identity(size: usize) -> TransitionMatrix {
+        let mut cells = vec![0; size * size];
+
+        for i in 0..size {
+            cells[i * size + i] = 1;
+        }
+
+        TransitionMatrix { size, cells }
+    }
+
+    fn multiply(
+        &self,
+        other: &TransitionMatrix,
+        arithmetic: Arithmetic,
+    ) -> Result<TransitionMatrix, Oopsie> {
+        let size = self.size;
+        let mut cells = vec![0; size * size];
+
+        for i in 0..size {
+            for k in 0..size {
+                let left = self.cells[i * size + k];
+                if left == 0 {
+                    continue;
+                }
+
+                for j in 0..size {
+                    let right = other.cells[k * size + j];
+                    if right != 0 {
+                        let product = arithmetic.mul(left, right)?;
+                        cells[i * size + j] = arithmetic.add(cells[i * size + j], product)?;
+                    }
+                }
+            }
+        }
+
+        Ok(TransitionMatrix { size, cells })
+    }
+
+    fn apply(&self, vector: &[u128], arithmetic: Arithmetic) -> Result<Vec<u128>, Oopsie> {
+        let mut result = vec![0; self.size];
+
+        for (to, slot) in result.iter_mut().enumerate() {
+            for (from, count) in vector.iter().enumerate() {
+                let cell = self.cells[to * self.size + from];
+                if cell != 0 && *count != 0 {
+                    *slot = arithmetic.add(*slot, arithmetic.mul(cell, *count)?)?;
+                }
+            }
+        }
 
-    (high - low) / 2
+        Ok(result)
+    }
+}
+
+fn reachable_pairs(initial: &State, transforms: &TransformDictionary) -> Vec<Pair> {
+    let mut pending: Vec<Pair> = initial.keys().copied().collect();
+    let mut seen = HashSet::new();
+
+    while let Some(pair) = pending.pop() {
+        if seen.insert(pair) {
+            if let Some(produced) = transforms.get(&pair) {
+                pending.extend(produced.iter());
+            }
+        }
+    }
+
+    let mut pairs: Vec<Pair> = seen.into_iter().collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+impl Polymer {
+    fn histogram_after(
+        &self,
+        steps: u64,
+        transforms: &TransformDictionary,
+        arithmetic: Arithmetic,
+    ) -> Result<HashMap<char, u128>, Oopsie> {
+        let pairs = reachable_pairs(&self.pairs, transforms);
+        let index: HashMap<Pair, usize> = pairs.iter().enumerate().map(|(i, p)| (*p, i)).collect();
+        let size = pairs.len();
+
+        let mut transition = TransitionMatrix {
+            size,
+            cells: vec![0; size * size],
+        };
+        for (from, pair) in pairs.iter().enumerate() {
+            match transforms.get(pair) {
+                None => transition.cells[from * size + from] += 1,
+                Some(produced) => {
+                    for to in produced.iter().map(|x| index[x]) {
+                        transition.cells[to * size + from] += 1;
+                    }
+                }
+            }
+        }
+
+        let mut counts = vec![0; size];
+        for (pair, count) in self.pairs.iter() {
+            counts[index[pair]] = arithmetic.reduce(*count as u128);
+        }
+
+        let mut power = transition;
+        let mut jump = TransitionMatrix::identity(size);
+        let mut remaining = steps;
+        while remaining > 0 {
+            if remaining & 1 == 1 {
+                jump = jump.multiply(&power, arithmetic)?;
+            }
+            remaining >>= 1;
+            if remaining > 0 {
+                power = power.multiply(&power, arithmetic)?;
+            }
+        }
+        let counts = jump.apply(&counts, arithmetic)?;
+
+        let mut histogram = HashMap::new();
+        for (pair, count) in pairs.iter().zip(counts) {
+            let entry = histogram.entry(pair.0).or_insert(0);
+            *entry = arithmetic.add(*entry, count)?;
+        }
+        let entry = histogram.entry(self.last).or_insert(0);
+        *entry = arithmetic.add(*entry, arithmetic.reduce(1))?;
+        if arithmetic == Arithmetic::Checked {
+            histogram.retain(|_, count| *count != 0);
+        }
+
+        Ok(histogram)
+    }
+
+    fn score_after(&self, steps: u64, transforms: &TransformDictionary) -> Result<u128, Oopsie> {
+        let histogram = self.histogram_after(steps, transforms, Arithmetic::Checked)?;
+        let high = extreme(&histogram, |count, best| count > best).0;
+        let low = extreme(&histogram, |count, best| count < best).0;
+
+        Ok(high - low)
+    }
+}
+
+#[test]
+fn matrix_stepping() {
+    let (base, transforms) = read_file(TEST_DATA).unwrap();
+    let transforms = build_transform_dictionary(transforms);
+    let polymer = Polymer::new(&base).unwrap();
+    let mut iterative = Polymer::new(&base).unwrap();
+
+    for steps in 0..=40 {
+        let expected: HashMap<char, u128> = iterative
+            .histogram()
+            .into_iter()
+            .map(|(element, count)| (element, count as u128))
+            .collect();
+        assert_eq!(
+            Ok(expected),
+            polymer.histogram_after(steps, &transforms, Arithmetic::Checked)
+        );
+        iterative = iterative.step(&transforms);
+    }
+
+    assert_eq!(Ok(1588), polymer.score_after(10, &transforms));
+    assert_eq!(Ok(2188189693529), polymer.score_after(40, &transforms));
+    assert_eq!(
+        Err(Oopsie::Overflow),
+        polymer.score_after(1_000_000_000_000, &transforms)
+    );
+
+    assert_eq!(Err(Oopsie::ZeroModulus), Arithmetic::modulo(0));
+    let modulus = Arithmetic::modulo(1_000_000_007).unwrap();
+    let forty = polymer.histogram_after(40, &transforms, modulus).unwrap();
+    for (element, count) in polymer.steps(40, &transforms).histogram() {
+        assert_eq!((count % 1_000_000_007) as u128, forty[&element]);
+    }
+    let total: u128 = polymer
+        .histogram_after(1_000_000_000_000, &transforms, modulus)
+        .unwrap()
+        .values()
+        .sum();
+    // the polymer has 3 * 2^n + 1 elements after n steps
+    let mut expected = 1u128;
+    let mut base = 2u128;
+    let mut exponent = 1_000_000_000_000u64;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            expected = expected * base % 1_000_000_007;
+        }
+        base = base * base % 1_000_000_007;
+        exponent >>= 1;
+    }
+    assert_eq!((3 * expected + 1) % 1_000_000_007, total % 1_000_000_007);
+
+    let inert = Polymer::new("NNNN").unwrap();
+    assert_eq!(
+        Ok(HashMap::from([('N', 4)])),
+        inert.histogram_after(
+            1_000_000_000_000,
+            &TransformDictionary::new(),
+            Arithmetic::Checked
+        )
+    );
+}
+
+fn materialize(
+    template: &str,
+    steps: usize,
+    transforms: &TransformDictionary,
+    limit: usize,
+) -> Result<String, Oopsie> {
+    let mut polymer: Vec<char> = template.chars().collect();
+
+    if polymer.len() > limit {
+        return Err(Oopsie::TooLong(limit));
+    }
+
+    for _ in 0..steps {
+        let mut next = Vec::with_capacity(polymer.len() * 2);
+
+        for pair in polymer.windows(2) {
+            next.push(pair[0]);
+            if let Some([(_, inject), _]) = transforms.get(&(pair[0], pair[1])) {
+                next.push(*inject);
+            }
+            if next.len() >= limit {
+                return Err(Oopsie::TooLong(limit));
+            }
+        }
+        next.extend(polymer.last());
+
+        polymer = next;
+    }
+
+    Ok(polymer.into_iter().collect())
+}
+
+fn char_at(
+    template: &str,
+    steps: usize,
+    index: u128,
+    transforms: &TransformDictionary,
+) -> Option<char> {
+    let chars: Vec<char> = template.chars().collect();
+    let initial = build_initial_state(template);
+    let pairs = reachable_pairs(&initial, transforms);
+
+    // lengths[k][pair] is how many elements `pair` expands to after k
+    // steps, not counting its trailing element
+    let mut lengths = vec![pairs
+        .iter()
+        .map(|p| (*p, 1u128))
+        .collect::<HashMap<Pair, u128>>()];
+    for k in 1..=steps {
+        let previous = &lengths[k - 1];
+        let current = pairs
+            .iter()
+            .map(|pair| match transforms.get(pair) {
+                None => (*pair, previous[pair]),
+                Some([p1, p2]) => (*pair, previous[p1].saturating_add(previous[p2])),
+            })
+            .collect();
+        lengths.push(current);
+    }
+
+    let mut remaining = index;
+    for pair in chars.windows(2).map(|x| (x[0], x[1])) {
+        let length = lengths[steps][&pair];
+        if remaining >= length {
+            remaining -= length;
+            continue;
+        }
+
+        let mut current = pair;
+        for k in (1..=steps).rev() {
+            match transforms.get(&current) {
+                None => break,
+                Some([p1, p2]) => {
+                    let left = lengths[k - 1][p1];
+                    if remaining < left {
+                        current = *p1;
+                    } else {
+                        remaining -= left;
+                        current = *p2;
+                    }
+                }
+            }
+        }
+
+        return Some(current.0);
+    }
+
+    if remaining == 0 {
+        chars.last().copied()
+    } else {
+        None
+    }
+}
+
+#[test]
+fn materialized_polymers() {
+    let (base, transforms) = read_file(TEST_DATA).unwrap();
+    let transforms = build_transform_dictionary(transforms);
+    let expected = [
+        "NNCB",
+        "NCNBCHB",
+        "NBCCNBBBCBHCB",
+        "NBBBCNCCNBBNBNBBCHBHHBCHB",
+        "NBBNBNBBCCNBCNCCNBBNBBNBBBNBBNBBCBHCBHHNHCBBCBHCB",
+    ];
+
+    for (steps, polymer) in expected.iter().enumerate() {
+        assert_eq!(
+            Ok(polymer.to_string()),
+            materialize(&base, steps, &transforms, 1000)
+        );
+    }
+
+    let template = Polymer::new(&base).unwrap();
+    for steps in 0..=8 {
+        let polymer = materialize(&base, steps, &transforms, 10_000).unwrap();
+        let counted = template.steps(steps, &transforms);
+        assert_eq!(counted.len(), polymer.len());
+        assert_eq!(
+            counted.histogram(),
+            Polymer::new(&polymer).unwrap().histogram()
+        );
+
+        for (idx, c) in polymer.chars().enumerate() {
+            assert_eq!(Some(c), char_at(&base, steps, idx as u128, &transforms));
+        }
+        assert_eq!(
+            None,
+            char_at(&base, steps, polymer.len() as u128, &transforms)
+        );
+    }
+
+    assert_eq!(
+        Err(Oopsie::TooLong(100)),
+        materialize(&base, 10, &transforms, 100)
+    );
+    assert_eq!(Some('B'), char_at(&base, 40, 3 << 40, &transforms));
+    assert_eq!(Some('N'), char_at(&base, 40, 0, &transforms));
+}
+
+#[test]
+fn rule_validation() {
+    let (base, transforms) = read_file(TEST_DATA).unwrap();
+    let report = validate_rules(&base, &transforms);
+    assert_eq!(vec!['B', 'C', 'H', 'N'], report.alphabet);
+    assert!(report.diagnostics.is_empty());
+    assert!(report.is_clean());
+
+    let (base, transforms) =
+        read_file("ABA\n\nAB -> C\nAB -> C\nBA -> A\nBA -> B\nXY -> Z\n").unwrap();
+    let report = validate_rules(&base, &transforms);
+    assert_eq!(vec!['A', 'B', 'C', 'X', 'Y', 'Z'], report.alphabet);
+    assert!(!report.is_clean());
+    assert!(report
+        .diagnostics
+        .contains(&Diagnostic::DuplicateRule(('A', 'B'), 'C')));
+    assert!(report
+        .diagnostics
+        .contains(&Diagnostic::ConflictingRules(('B', 'A'), vec!['A', 'B'])));
+    assert!(report
+        .diagnostics
+        .contains(&Diagnostic::UncoveredPair(('Z', 'Z'))));
+    assert!(report
+        .diagnostics
+        .contains(&Diagnostic::UnreachableRule(('X', 'Y'))));
+    let inert: Vec<&Diagnostic> = report
+        .diagnostics
+        .iter()
+        .filter(|x| matches!(x, Diagnostic::InertReachablePair(_)))
+        .collect();
+    assert_eq!(
+        vec![
+            &Diagnostic::InertReachablePair(('A', 'A')),
+            &Diagnostic::InertReachablePair(('A', 'C')),
+            &Diagnostic::InertReachablePair(('B', 'B')),
+            &Diagnostic::InertReachablePair(('C', 'B')),
+        ],
+        inert
+    );
+    assert_eq!(
+        "rule AB -> C appears more than once",
+        report.diagnostics[0].to_string()
+    );
+
+    assert!(matches!(
+        read_file("AB\n\nAB -> \n"),
+        Err(Oopsie::BadTransform(_))
+    ));
+    assert!(matches!(
+        read_file("AB\n\nABC -> D\n"),
+        Err(Oopsie::BadTransform(_))
+    ));
+}
+
+#[test]
+fn polymer_statistics() {
+    let (base, transforms) = read_file(TEST_DATA).unwrap();
+    let transforms = build_transform_dictionary(transforms);
+    let polymer = Polymer::new(&base).unwrap();
+
+    assert_eq!(
+        HashMap::from([('N', 2), ('C', 1), ('B', 1)]),
+        polymer.histogram()
+    );
+    let one = polymer.step(&transforms);
+    assert_eq!(7, one.len());
+    assert_eq!(
+        HashMap::from([('N', 2), ('C', 2), ('B', 2), ('H', 1)]),
+        one.histogram()
+    );
+    assert_eq!((2, vec!['B', 'C', 'N']), one.most_common());
+    assert_eq!((1, vec!['H']), one.least_common());
+
+    let ten = polymer.steps(10, &transforms);
+    assert_eq!(3073, ten.len());
+    assert_eq!((1749, vec!['B']), ten.most_common());
+    assert_eq!((161, vec!['H']), ten.least_common());
+    assert_eq!(1588, ten.score());
+    assert_eq!(2188189693529, polymer.steps(40, &transforms).score());
+
+    // the ends of the template are the rarest elements here, which the
+    // halved pair counts used to get wrong
+    let ends = Polymer::new("ABBA").unwrap();
+    assert_eq!(
+        HashMap::from([('A', 2), ('B', 2)]),
+        ends.steps(3, &TransformDictionary::new()).histogram()
+    );
+    let single = Polymer::new("Z").unwrap();
+    assert_eq!((1, vec!['Z']), single.steps(5, &transforms).most_common());
+    assert!(Polymer::new("").is_err());
 }
 
 fn main() -> Result<(), Oopsie> {
     let (test_base, test_transforms) = read_file(TEST_DATA)?;
     let (real_base, real_transforms) = read_file(REAL_DATA)?;
     let test_initial = build_initial_state(&test_base);
-    let real_initial = build_initial_state(&real_base);
+    for (name, base, transforms) in [
+        ("Test", &test_base, &test_transforms),
+        ("Real", &real_base, &real_transforms),
+    ] {
+        let report = validate_rules(base, transforms);
+        let verdict = if report.is_clean() {
+            "clean"
+        } else {
+            "problems"
+        };
+        println!("{} rules ({}): {}", name, verdict, report);
+    }
     let test_transform_dict = build_transform_dictionary(test_transforms);
     let real_transform_dict = build_transform_dictionary(real_transforms);
 
@@ -156,28 +841,48 @@ fn main() -> Result<(), Oopsie> {
     println!("one step: {:?}", step(&test_initial, &test_transform_dict));
     println!(
         "four steps: {:?}",
-        steps(&test_initial, 4, &test_transform_dict)
+        Polymer::new(&test_base)?
+            .steps(4, &test_transform_dict)
+            .pairs
     );
+    let test_polymer = Polymer::new(&test_base)?.steps(10, &test_transform_dict);
+    println!("Length at 10 steps: {:?}", test_polymer.len());
+    println!("Counts at 10 steps: {:?}", test_polymer.histogram());
+    println!("Most common at 10 steps: {:?}", test_polymer.most_common());
     println!(
-        "Length at 10 steps: {:?}",
-        steps(&test_initial, 10, &test_transform_dict).len()
+        "Least common at 10 steps: {:?}",
+        test_polymer.least_common()
     );
+    println!("Score at 10 steps: {:?}", test_polymer.score());
+
+    let real_polymer = Polymer::new(&real_base)?;
     println!(
-        "Counts at 10 steps: {:?}",
-        counts(&steps(&test_initial, 10, &test_transform_dict))
+        "Real score at 10 steps: {:?}",
+        real_polymer.steps(10, &real_transform_dict).score()
     );
     println!(
-        "Score at 10 steps: {:?}",
-        score(&counts(&steps(&test_initial, 10, &test_transform_dict)))
+        "Real score at 40 steps: {:?}",
+        real_polymer.steps(40, &real_transform_dict).score()
     );
-
     println!(
-        "Real score at 10 steps: {:?}",
-        score(&counts(&steps(&real_initial, 10, &real_transform_dict)))
+        "Test polymer after 4 steps: {}",
+        materialize(&test_base, 4, &test_transform_dict, 100)?
     );
     println!(
-        "Real score at 40 steps: {:?}",
-        score(&counts(&steps(&real_initial, 40, &real_transform_dict)))
+        "Real element 10^12 after 40 steps: {:?}",
+        char_at(&real_base, 40, 1_000_000_000_000, &real_transform_dict)
+    );
+    println!(
+        "Real score at 40 steps (matrix): {:?}",
+        real_polymer.score_after(40, &real_transform_dict)?
+    );
+    println!(
+        "Real counts at 10^12 steps, mod 1000000007: {:?}",
+        real_polymer.histogram_after(
+            1_000_000_000_000,
+            &real_transform_dict,
+            Arithmetic::modulo(1_000_000_007)?
+        )?
     );
     Ok(())
 }
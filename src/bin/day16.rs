@@ -1,8 +1,15 @@
+#[cfg(test)]
+use advent2021::prng::Prng;
+use core::fmt;
 use std::collections::VecDeque;
+use std::hint::black_box;
 use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Instant;
 use thiserror::Error;
 
 const REAL_DATA: &str = include_str!("../../data/day16.txt");
+const BENCHMARK_ROUNDS: usize = 10_000;
 
 #[derive(Debug, Error, PartialEq)]
 enum Oopsie {
@@ -12,6 +19,22 @@ enum Oopsie {
     RanOuttaBits(&'static str, usize),
     #[error("Invalid split attempt: stream length is {0}, but requested split at {1}")]
     InvalidSplit(usize, usize),
+    #[error("Value {0} doesn't fit in {1} bits")]
+    ValueTooLarge(u64, usize),
+    #[error("Literal value doesn't fit in 64 bits")]
+    LiteralTooLarge,
+    #[error("Unknown operator type {0}")]
+    UnknownOperator(u8),
+    #[error("Type {0} is reserved for literals")]
+    ReservedTypeId(u8),
+    #[error("Operator {0} can't take {1} operands")]
+    BadArity(&'static str, usize),
+    #[error("Operator {0} has no defined result for its operands")]
+    UndefinedResult(&'static str),
+    #[error("Ran out of stack executing instruction {0}")]
+    StackUnderflow(usize),
+    #[error("Program finished with {0} values on the stack")]
+    LeftoverStack(usize),
 }
 
 struct BitStream {
@@ -79,9 +102,190 @@ impl BitStream {
     fn is_empty(&self) -> bool {
         self.bits.is_empty()
     }
+
+    fn new() -> BitStream {
+        BitStream {
+            bits: VecDeque::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.bits.len()
+    }
+
+    fn push_bit(&mut self, bit: bool) {
+        self.bits.push_back(bit);
+    }
+
+    fn push_bits(&mut self, value: u64, bits: usize) -> Result<(), Oopsie> {
+        if bits < 64 && value >> bits != 0 {
+            return Err(Oopsie::ValueTooLarge(value, bits));
+        }
+
+        for i in (0..bits).rev() {
+            self.bits.push_back((value >> i) & 1 == 1);
+        }
+
+        Ok(())
+    }
+
+    fn append(&mut self, other: &mut BitStream) {
+        self.bits.append(&mut other.bits);
+    }
 }
 
-#[derive(Debug, PartialEq)]
+impl fmt::Display for BitStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut digit = 0;
+
+        for (idx, bit) in self.bits.iter().enumerate() {
+            digit = (digit << 1) | (*bit as u32);
+            if idx % 4 == 3 {
+                write!(f, "{:X}", digit)?;
+                digit = 0;
+            }
+        }
+
+        let leftover = self.bits.len() % 4;
+        if leftover != 0 {
+            write!(f, "{:X}", digit << (4 - leftover))?;
+        }
+
+        if self.bits.len().div_ceil(4) % 2 == 1 {
+            write!(f, "0")?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum LengthType {
+    TotalBits,
+    PacketCount,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Arity {
+    Exactly(usize),
+    AtLeast(usize),
+}
+
+impl Arity {
+    fn accepts(&self, count: usize) -> bool {
+        match self {
+            Arity::Exactly(x) => count == *x,
+            Arity::AtLeast(x) => count >= *x,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Operator {
+    name: &'static str,
+    arity: Arity,
+    apply: fn(&[u64]) -> Option<u64>,
+    build: fn(u8, u8, Vec<Message>) -> Message,
+}
+
+impl Operator {
+    fn new(name: &'static str, arity: Arity, apply: fn(&[u64]) -> Option<u64>) -> Operator {
+        Operator {
+            name,
+            arity,
+            apply,
+            build: Message::Sequence,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct OperatorRegistry {
+    operators: [Option<Operator>; 8],
+}
+
+impl Default for OperatorRegistry {
+    fn default() -> Self {
+        let mut registry = OperatorRegistry::empty();
+        let builtins = [
+            Operator {
+                build: |v, _, seq| Message::Sum(v, seq),
+                ..Operator::new("sum", Arity::AtLeast(0), |xs| Some(xs.iter().sum()))
+            },
+            Operator {
+                build: |v, _, seq| Message::Product(v, seq),
+                ..Operator::new("product", Arity::AtLeast(0), |xs| Some(xs.iter().product()))
+            },
+            Operator {
+                build: |v, _, seq| Message::Minimum(v, seq),
+                ..Operator::new("minimum", Arity::AtLeast(0), |xs| xs.iter().min().copied())
+            },
+            Operator {
+                build: |v, _, seq| Message::Maximum(v, seq),
+                ..Operator::new("maximum", Arity::AtLeast(0), |xs| xs.iter().max().copied())
+            },
+            Operator {
+                build: |v, _, seq| Message::GreaterThan(v, seq),
+                ..Operator::new("greater than", Arity::Exactly(2), |xs| {
+                    Some((xs[0] > xs[1]) as u64)
+                })
+            },
+            Operator {
+                build: |v, _, seq| Message::LessThan(v, seq),
+                ..Operator::new("less than", Arity::Exactly(2), |xs| {
+                    Some((xs[0] < xs[1]) as u64)
+                })
+            },
+            Operator {
+                build: |v, _, seq| Message::EqualTo(v, seq),
+                ..Operator::new("equal to", Arity::Exactly(2), |xs| {
+                    Some((xs[0] == xs[1]) as u64)
+                })
+            },
+        ];
+
+        for (type_id, operator) in [0, 1, 2, 3, 5, 6, 7].into_iter().zip(builtins) {
+            registry
+                .register(type_id, operator)
+                .expect("built-in operators use valid type ids");
+        }
+
+        registry
+    }
+}
+
+impl OperatorRegistry {
+    // the built-in table, built once and shared by everything that doesn't
+    // bring its own registry
+    fn builtins() -> &'static OperatorRegistry {
+        static BUILTINS: OnceLock<OperatorRegistry> = OnceLock::new();
+        BUILTINS.get_or_init(OperatorRegistry::default)
+    }
+
+    fn empty() -> OperatorRegistry {
+        OperatorRegistry {
+            operators: [None; 8],
+        }
+    }
+
+    fn register(&mut self, type_id: u8, operator: Operator) -> Result<Option<Operator>, Oopsie> {
+        if type_id == 4 {
+            return Err(Oopsie::ReservedTypeId(type_id));
+        }
+
+        let slot = self
+            .operators
+            .get_mut(type_id as usize)
+            .ok_or(Oopsie::ValueTooLarge(type_id as u64, 3))?;
+        Ok(slot.replace(operator))
+    }
+
+    fn get(&self, type_id: u8) -> Option<&Operator> {
+        self.operators.get(type_id as usize)?.as_ref()
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 enum Message {
     Literal(u8, u64),
     Sum(u8, Vec<Message>),
@@ -95,19 +299,76 @@ enum Message {
 }
 
 impl Message {
-    fn sequence(version: u8, type_id: u8, sequence: Vec<Message>) -> Message {
-        match type_id {
-            0 => Message::Sum(version, sequence),
-            1 => Message::Product(version, sequence),
-            2 => Message::Minimum(version, sequence),
-            3 => Message::Maximum(version, sequence),
-            5 if sequence.len() == 2 => Message::GreaterThan(version, sequence),
-            6 if sequence.len() == 2 => Message::LessThan(version, sequence),
-            7 if sequence.len() == 2 => Message::EqualTo(version, sequence),
+    fn sequence(
+        registry: &OperatorRegistry,
+        version: u8,
+        type_id: u8,
+        sequence: Vec<Message>,
+    ) -> Message {
+        match registry.get(type_id) {
+            Some(operator) if operator.arity.accepts(sequence.len()) => {
+                (operator.build)(version, type_id, sequence)
+            }
             _ => Message::Sequence(version, type_id, sequence),
         }
     }
 
+    fn version(&self) -> u8 {
+        match self {
+            Message::Literal(x, _) => *x,
+            Message::Sum(x, _) => *x,
+            Message::Product(x, _) => *x,
+            Message::Minimum(x, _) => *x,
+            Message::Maximum(x, _) => *x,
+            Message::GreaterThan(x, _) => *x,
+            Message::LessThan(x, _) => *x,
+            Message::EqualTo(x, _) => *x,
+            Message::Sequence(x, _, _) => *x,
+        }
+    }
+
+    fn type_id(&self) -> u8 {
+        match self {
+            Message::Sum(_, _) => 0,
+            Message::Product(_, _) => 1,
+            Message::Minimum(_, _) => 2,
+            Message::Maximum(_, _) => 3,
+            Message::Literal(_, _) => 4,
+            Message::GreaterThan(_, _) => 5,
+            Message::LessThan(_, _) => 6,
+            Message::EqualTo(_, _) => 7,
+            Message::Sequence(_, x, _) => *x,
+        }
+    }
+
+    fn subpackets(&self) -> &[Message] {
+        match self {
+            Message::Literal(_, _) => &[],
+            Message::Sum(_, seq) => seq,
+            Message::Product(_, seq) => seq,
+            Message::Minimum(_, seq) => seq,
+            Message::Maximum(_, seq) => seq,
+            Message::GreaterThan(_, seq) => seq,
+            Message::LessThan(_, seq) => seq,
+            Message::EqualTo(_, seq) => seq,
+            Message::Sequence(_, _, seq) => seq,
+        }
+    }
+
+    fn into_subpackets(self) -> Vec<Message> {
+        match self {
+            Message::Literal(_, _) => Vec::new(),
+            Message::Sum(_, seq) => seq,
+            Message::Product(_, seq) => seq,
+            Message::Minimum(_, seq) => seq,
+            Message::Maximum(_, seq) => seq,
+            Message::GreaterThan(_, seq) => seq,
+            Message::LessThan(_, seq) => seq,
+            Message::EqualTo(_, seq) => seq,
+            Message::Sequence(_, _, seq) => seq,
+        }
+    }
+
     fn version_sum(&self) -> usize {
         match self {
             Message::Literal(x, _) => *x as usize,
@@ -139,42 +400,319 @@ impl Message {
     }
 
     fn eval(&self) -> u64 {
-        match self {
-            Message::Literal(_, x) => *x,
-            Message::Sum(_, seq) => seq.iter().map(|x| x.eval()).sum(),
-            Message::Product(_, seq) => seq.iter().map(|x| x.eval()).product(),
-            Message::Minimum(_, seq) => seq.iter().map(|x| x.eval()).min().unwrap(),
-            Message::Maximum(_, seq) => seq.iter().map(|x| x.eval()).max().unwrap(),
-            Message::GreaterThan(_, seq) => {
-                if seq[0].eval() > seq[1].eval() {
-                    1
-                } else {
-                    0
+        self.evaluate(OperatorRegistry::builtins())
+            .unwrap_or_else(|e| panic!("Tried to evaluate invalid message: {}", e))
+    }
+
+    fn evaluate(&self, registry: &OperatorRegistry) -> Result<u64, Oopsie> {
+        if let Message::Literal(_, x) = self {
+            return Ok(*x);
+        }
+
+        let type_id = self.type_id();
+        let operator = registry
+            .get(type_id)
+            .ok_or(Oopsie::UnknownOperator(type_id))?;
+        let subpackets = self.subpackets();
+
+        if !operator.arity.accepts(subpackets.len()) {
+            return Err(Oopsie::BadArity(operator.name, subpackets.len()));
+        }
+
+        let values = subpackets
+            .iter()
+            .map(|x| x.evaluate(registry))
+            .collect::<Result<Vec<u64>, Oopsie>>()?;
+
+        (operator.apply)(&values).ok_or(Oopsie::UndefinedResult(operator.name))
+    }
+
+    fn encode(&self, length_type: LengthType) -> Result<BitStream, Oopsie> {
+        let mut stream = BitStream::new();
+        self.encode_into(&mut stream, length_type)?;
+        Ok(stream)
+    }
+
+    fn encode_into(&self, stream: &mut BitStream, length_type: LengthType) -> Result<(), Oopsie> {
+        stream.push_bits(self.version() as u64, 3)?;
+        stream.push_bits(self.type_id() as u64, 3)?;
+
+        if let Message::Literal(_, value) = self {
+            let mut groups = Vec::new();
+            let mut remaining = *value;
+
+            loop {
+                groups.push(remaining & 0xf);
+                remaining >>= 4;
+                if remaining == 0 {
+                    break;
                 }
             }
-            Message::LessThan(_, seq) => {
-                if seq[0].eval() < seq[1].eval() {
-                    1
-                } else {
-                    0
+
+            for (idx, group) in groups.iter().enumerate().rev() {
+                stream.push_bit(idx != 0);
+                stream.push_bits(*group, 4)?;
+            }
+
+            return Ok(());
+        }
+
+        let subpackets = self.subpackets();
+        match length_type {
+            LengthType::TotalBits => {
+                let mut body = BitStream::new();
+                for subpacket in subpackets.iter() {
+                    subpacket.encode_into(&mut body, length_type)?;
                 }
+                stream.push_bit(false);
+                stream.push_bits(body.len() as u64, 15)?;
+                stream.append(&mut body);
             }
-            Message::EqualTo(_, seq) => {
-                if seq[0].eval() == seq[1].eval() {
-                    1
-                } else {
-                    0
+            LengthType::PacketCount => {
+                stream.push_bit(true);
+                stream.push_bits(subpackets.len() as u64, 11)?;
+                for subpacket in subpackets.iter() {
+                    subpacket.encode_into(stream, length_type)?;
                 }
             }
-            Message::Sequence(_, _, _) => panic!("Tried to evaluate unknown sequence!"),
         }
+
+        Ok(())
+    }
+
+    fn optimize(&self) -> Message {
+        match self {
+            Message::Literal(version, value) => Message::Literal(*version, *value),
+            Message::Sum(version, seq) => Message::optimize_associative(
+                *version,
+                0,
+                Message::Sum,
+                seq,
+                Some(0),
+                u64::checked_add,
+            ),
+            Message::Product(version, seq) => Message::optimize_associative(
+                *version,
+                1,
+                Message::Product,
+                seq,
+                Some(1),
+                u64::checked_mul,
+            ),
+            Message::Minimum(version, seq) => {
+                Message::optimize_associative(*version, 2, Message::Minimum, seq, None, |a, b| {
+                    Some(a.min(b))
+                })
+            }
+            Message::Maximum(version, seq) => {
+                Message::optimize_associative(*version, 3, Message::Maximum, seq, None, |a, b| {
+                    Some(a.max(b))
+                })
+            }
+            Message::GreaterThan(version, seq) => {
+                Message::optimize_comparison(*version, Message::GreaterThan, seq, |a, b| a > b)
+            }
+            Message::LessThan(version, seq) => {
+                Message::optimize_comparison(*version, Message::LessThan, seq, |a, b| a < b)
+            }
+            Message::EqualTo(version, seq) => {
+                Message::optimize_comparison(*version, Message::EqualTo, seq, |a, b| a == b)
+            }
+            Message::Sequence(version, type_id, seq) => Message::Sequence(
+                *version,
+                *type_id,
+                seq.iter().map(Message::optimize).collect(),
+            ),
+        }
+    }
+
+    // the built-in operators are folded with their built-in meaning, so the
+    // result is rebuilt with the same variant rather than looked up by type id
+    fn optimize_associative(
+        version: u8,
+        type_id: u8,
+        build: fn(u8, Vec<Message>) -> Message,
+        seq: &[Message],
+        identity: Option<u64>,
+        combine: fn(u64, u64) -> Option<u64>,
+    ) -> Message {
+        let mut flattened = Vec::with_capacity(seq.len());
+
+        for subpacket in seq.iter().map(Message::optimize) {
+            if subpacket.type_id() == type_id {
+                flattened.extend(subpacket.into_subpackets());
+            } else {
+                flattened.push(subpacket);
+            }
+        }
+
+        let mut constant = None;
+        let mut operands = Vec::with_capacity(flattened.len());
+
+        for subpacket in flattened.into_iter() {
+            match subpacket {
+                Message::Literal(v, value) => match constant {
+                    None => constant = Some(value),
+                    Some(current) => match combine(current, value) {
+                        Some(combined) => constant = Some(combined),
+                        None => operands.push(Message::Literal(v, value)),
+                    },
+                },
+                _ => operands.push(subpacket),
+            }
+        }
+
+        if let Some(value) = constant {
+            if operands.is_empty() || constant != identity {
+                operands.push(Message::Literal(version, value));
+            }
+        }
+
+        if operands.len() == 1 {
+            return operands.pop().unwrap();
+        }
+
+        build(version, operands)
     }
+
+    fn optimize_comparison(
+        version: u8,
+        build: fn(u8, Vec<Message>) -> Message,
+        seq: &[Message],
+        compare: fn(u64, u64) -> bool,
+    ) -> Message {
+        let operands: Vec<Message> = seq.iter().map(Message::optimize).collect();
+
+        if let [Message::Literal(_, a), Message::Literal(_, b)] = operands[..] {
+            return Message::Literal(version, compare(a, b) as u64);
+        }
+
+        build(version, operands)
+    }
+}
+
+// operators are looked up by type id in whatever registry the program is
+// compiled and run against, so dialects work on the VM too
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Instruction {
+    Push(u64),
+    Apply(u8, usize),
+}
+
+#[derive(Debug, PartialEq)]
+struct Program {
+    code: Vec<Instruction>,
 }
 
-impl<'a> TryFrom<&'a mut BitStream> for Message {
+impl Program {
+    fn compile(message: &Message, registry: &OperatorRegistry) -> Result<Program, Oopsie> {
+        let mut code = Vec::new();
+        let mut pending = vec![(message, false)];
+
+        while let Some((message, expanded)) = pending.pop() {
+            let subpackets = message.subpackets();
+
+            if let Message::Literal(_, x) = message {
+                code.push(Instruction::Push(*x));
+            } else if expanded {
+                code.push(Instruction::Apply(message.type_id(), subpackets.len()));
+            } else {
+                let type_id = message.type_id();
+                let operator = registry
+                    .get(type_id)
+                    .ok_or(Oopsie::UnknownOperator(type_id))?;
+                if !operator.arity.accepts(subpackets.len()) {
+                    return Err(Oopsie::BadArity(operator.name, subpackets.len()));
+                }
+
+                pending.push((message, true));
+                for subpacket in subpackets.iter().rev() {
+                    pending.push((subpacket, false));
+                }
+            }
+        }
+
+        Ok(Program { code })
+    }
+
+    fn run(&self, registry: &OperatorRegistry) -> Result<u64, Oopsie> {
+        let mut stack = Vec::new();
+
+        for (idx, instruction) in self.code.iter().enumerate() {
+            match *instruction {
+                Instruction::Push(x) => stack.push(x),
+                Instruction::Apply(type_id, count) => {
+                    let operator = registry
+                        .get(type_id)
+                        .ok_or(Oopsie::UnknownOperator(type_id))?;
+                    if !operator.arity.accepts(count) {
+                        return Err(Oopsie::BadArity(operator.name, count));
+                    }
+
+                    let start = stack
+                        .len()
+                        .checked_sub(count)
+                        .ok_or(Oopsie::StackUnderflow(idx))?;
+                    let value = (operator.apply)(&stack[start..])
+                        .ok_or(Oopsie::UndefinedResult(operator.name))?;
+                    stack.truncate(start);
+                    stack.push(value);
+                }
+            }
+        }
+
+        match stack.len() {
+            1 => Ok(stack[0]),
+            0 => Err(Oopsie::StackUnderflow(self.code.len())),
+            x => Err(Oopsie::LeftoverStack(x)),
+        }
+    }
+}
+
+struct OptimizationReport {
+    original_bits: usize,
+    optimized_bits: usize,
+}
+
+impl OptimizationReport {
+    fn new(
+        original: &Message,
+        optimized: &Message,
+        length_type: LengthType,
+    ) -> Result<OptimizationReport, Oopsie> {
+        Ok(OptimizationReport {
+            original_bits: original.encode(length_type)?.len(),
+            optimized_bits: optimized.encode(length_type)?.len(),
+        })
+    }
+
+    fn bits_saved(&self) -> usize {
+        self.original_bits.saturating_sub(self.optimized_bits)
+    }
+}
+
+impl fmt::Display for OptimizationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} bits -> {} bits ({} saved)",
+            self.original_bits,
+            self.optimized_bits,
+            self.bits_saved()
+        )
+    }
+}
+
+impl TryFrom<&mut BitStream> for Message {
     type Error = Oopsie;
 
     fn try_from(value: &mut BitStream) -> Result<Self, Self::Error> {
+        Message::decode(value, OperatorRegistry::builtins())
+    }
+}
+
+impl Message {
+    fn decode(value: &mut BitStream, registry: &OperatorRegistry) -> Result<Message, Oopsie> {
         let version = value.next_u8(3)?;
         let type_id = value.next_u8(3)?;
 
@@ -184,6 +722,9 @@ impl<'a> TryFrom<&'a mut BitStream> for Message {
 
             while keep_going {
                 keep_going = value.next_bit()?;
+                if literal >> 60 != 0 {
+                    return Err(Oopsie::LiteralTooLarge);
+                }
                 literal = (literal << 4) + value.next_u64(4)?;
             }
 
@@ -196,18 +737,18 @@ impl<'a> TryFrom<&'a mut BitStream> for Message {
                 let subpart_count = value.next_u16(11)?;
 
                 for _ in 0..subpart_count {
-                    seq.push(Message::try_from(&mut *value)?);
+                    seq.push(Message::decode(value, registry)?);
                 }
             } else {
                 let subpart_len = value.next_u16(15)? as usize;
                 let mut my_bits = value.take(subpart_len)?;
 
                 while !my_bits.is_empty() {
-                    seq.push(Message::try_from(&mut my_bits)?);
+                    seq.push(Message::decode(&mut my_bits, registry)?);
                 }
             }
 
-            Ok(Message::sequence(version, type_id, seq))
+            Ok(Message::sequence(registry, version, type_id, seq))
         }
     }
 }
@@ -276,9 +817,490 @@ fn example_tests() {
     );
 }
 
+#[test]
+fn evaluation_examples() {
+    let examples = [
+        ("C200B40A82", 3),
+        ("04005AC33890", 54),
+        ("880086C3E88112", 7),
+        ("CE00C43D881120", 9),
+        ("D8005AC2A8F0", 1),
+        ("F600BC2D8F", 0),
+        ("9C005AC2F8F0", 0),
+        ("9C0141080250320F1802104A08", 1),
+    ];
+
+    for (input, expected) in examples {
+        assert_eq!(expected, Message::from_str(input).unwrap().eval());
+    }
+}
+
+#[test]
+fn encoding_round_trips() {
+    for (input, length_type) in [
+        ("D2FE28", LengthType::TotalBits),
+        ("38006F45291200", LengthType::TotalBits),
+        ("EE00D40C823060", LengthType::PacketCount),
+    ] {
+        let message = Message::from_str(input).unwrap();
+        assert_eq!(input, message.encode(length_type).unwrap().to_string());
+    }
+
+    let message = Message::from_str("9C0141080250320F1802104A08").unwrap();
+    for length_type in [LengthType::TotalBits, LengthType::PacketCount] {
+        let encoded = message.encode(length_type).unwrap().to_string();
+        assert_eq!(Ok(message.clone()), Message::from_str(&encoded));
+    }
+
+    assert_eq!(
+        Err(Oopsie::ValueTooLarge(8, 3)),
+        Message::Literal(8, 0)
+            .encode(LengthType::PacketCount)
+            .map(|x| x.len())
+    );
+
+    let mut oversized = BitStream::new();
+    oversized.push_bits(4, 6).unwrap();
+    for _ in 0..16 {
+        oversized.push_bit(true);
+        oversized.push_bits(0xf, 4).unwrap();
+    }
+    oversized.push_bit(false);
+    oversized.push_bits(0, 4).unwrap();
+    assert_eq!(
+        Err(Oopsie::LiteralTooLarge),
+        Message::try_from(&mut oversized)
+    );
+}
+
+#[test]
+fn optimization() {
+    let sum = Message::from_str("C200B40A82").unwrap();
+    let optimized = sum.optimize();
+    assert_eq!(Message::Literal(6, 3), optimized);
+    let report = OptimizationReport::new(&sum, &optimized, LengthType::TotalBits).unwrap();
+    assert_eq!(33, report.bits_saved());
+
+    let nested = Message::Sum(
+        1,
+        vec![
+            Message::Literal(2, 0),
+            Message::Sum(
+                3,
+                vec![
+                    Message::Maximum(4, vec![Message::Literal(5, 7)]),
+                    Message::Product(
+                        6,
+                        vec![
+                            Message::Literal(7, 1),
+                            Message::Sequence(0, 9, vec![Message::Literal(1, 4)]),
+                        ],
+                    ),
+                ],
+            ),
+            Message::LessThan(2, vec![Message::Literal(3, 1), Message::Literal(4, 2)]),
+        ],
+    );
+    assert_eq!(
+        Message::Sum(
+            1,
+            vec![
+                Message::Sequence(0, 9, vec![Message::Literal(1, 4)]),
+                Message::Literal(1, 8)
+            ]
+        ),
+        nested.optimize()
+    );
+
+    let overflowing = Message::Product(
+        1,
+        vec![Message::Literal(2, u64::MAX), Message::Literal(3, 2)],
+    );
+    assert_eq!(
+        Message::Product(
+            1,
+            vec![Message::Literal(3, 2), Message::Literal(1, u64::MAX)]
+        ),
+        overflowing.optimize()
+    );
+
+    let comparison = Message::GreaterThan(
+        1,
+        vec![
+            Message::Sum(2, vec![Message::Literal(3, 1), Message::Literal(4, 2)]),
+            Message::Literal(5, 3),
+        ],
+    );
+    assert_eq!(Message::Literal(1, 0), comparison.optimize());
+
+    for input in [
+        "04005AC33890",
+        "880086C3E88112",
+        "CE00C43D881120",
+        "D8005AC2A8F0",
+        "9C0141080250320F1802104A08",
+    ] {
+        let message = Message::from_str(input).unwrap();
+        let optimized = message.optimize();
+        let encoded = optimized.encode(LengthType::PacketCount).unwrap();
+        assert_eq!(message.eval(), optimized.eval());
+        assert_eq!(Ok(optimized), Message::from_str(&encoded.to_string()));
+    }
+}
+
+#[cfg(test)]
+fn dialect_registry() -> OperatorRegistry {
+    let mut registry = OperatorRegistry::default();
+    let dialect = [
+        Operator::new("xor", Arity::AtLeast(1), |xs| {
+            Some(xs.iter().fold(0, |acc, x| acc ^ x))
+        }),
+        Operator::new("modulo", Arity::Exactly(2), |xs| xs[0].checked_rem(xs[1])),
+        Operator::new("average", Arity::AtLeast(1), |xs| {
+            Some(xs.iter().sum::<u64>() / xs.len() as u64)
+        }),
+        Operator::new("if-then-else", Arity::Exactly(3), |xs| {
+            Some(if xs[0] != 0 { xs[1] } else { xs[2] })
+        }),
+    ];
+
+    for (type_id, operator) in [2, 3, 5, 6].into_iter().zip(dialect) {
+        registry.register(type_id, operator).unwrap();
+    }
+
+    registry
+}
+
+#[test]
+fn operator_registry() {
+    let builtin = OperatorRegistry::default();
+    assert!(std::ptr::eq(
+        OperatorRegistry::builtins(),
+        OperatorRegistry::builtins()
+    ));
+    for input in [
+        "C200B40A82",
+        "04005AC33890",
+        "880086C3E88112",
+        "CE00C43D881120",
+        "D8005AC2A8F0",
+        "F600BC2D8F",
+        "9C005AC2F8F0",
+        "9C0141080250320F1802104A08",
+    ] {
+        let message = Message::from_str(input).unwrap();
+        assert_eq!(Ok(message.eval()), message.evaluate(&builtin));
+    }
+    assert_eq!(
+        Ok(Message::Sequence(0, 5, vec![Message::Literal(0, 1); 3])),
+        Message::from_str(
+            &Message::Sequence(0, 5, vec![Message::Literal(0, 1); 3])
+                .encode(LengthType::PacketCount)
+                .unwrap()
+                .to_string()
+        )
+    );
+
+    let dialect = dialect_registry();
+    let program = Message::Sequence(
+        1,
+        6,
+        vec![
+            Message::Sequence(2, 2, vec![Message::Literal(3, 5), Message::Literal(3, 5)]),
+            Message::Sequence(2, 3, vec![Message::Literal(3, 17), Message::Literal(3, 5)]),
+            Message::Sequence(
+                2,
+                5,
+                vec![
+                    Message::Literal(3, 1),
+                    Message::Literal(3, 2),
+                    Message::Literal(3, 6),
+                ],
+            ),
+        ],
+    );
+    let encoded = program.encode(LengthType::TotalBits).unwrap();
+    let decoded = Message::decode(
+        &mut BitStream::from_str(&encoded.to_string()).unwrap(),
+        &dialect,
+    );
+    assert_eq!(Ok(program.clone()), decoded);
+    assert_eq!(Ok(3), program.evaluate(&dialect));
+    assert_eq!(
+        Err(Oopsie::BadArity("less than", 3)),
+        program.evaluate(&builtin)
+    );
+    assert_eq!(
+        Err(Oopsie::UndefinedResult("modulo")),
+        Message::Sequence(0, 3, vec![Message::Literal(0, 1), Message::Literal(0, 0)])
+            .evaluate(&dialect)
+    );
+    assert_eq!(
+        Err(Oopsie::UnknownOperator(2)),
+        Message::Minimum(0, vec![Message::Literal(0, 1)]).evaluate(&OperatorRegistry::empty())
+    );
+
+    let mut registry = OperatorRegistry::default();
+    let modulo = *dialect.get(3).unwrap();
+    assert!(matches!(
+        registry.register(4, modulo),
+        Err(Oopsie::ReservedTypeId(4))
+    ));
+    assert!(matches!(
+        registry.register(8, modulo),
+        Err(Oopsie::ValueTooLarge(8, 3))
+    ));
+    assert_eq!(
+        Some("maximum"),
+        registry.register(3, modulo).unwrap().map(|x| x.name)
+    );
+}
+
+#[test]
+fn bytecode() {
+    let message = Message::from_str("9C0141080250320F1802104A08").unwrap();
+    let builtin = OperatorRegistry::builtins();
+    let program = Program::compile(&message, builtin).unwrap();
+    assert_eq!(
+        vec![
+            Instruction::Push(1),
+            Instruction::Push(3),
+            Instruction::Apply(0, 2),
+            Instruction::Push(2),
+            Instruction::Push(2),
+            Instruction::Apply(1, 2),
+            Instruction::Apply(7, 2),
+        ],
+        program.code
+    );
+    assert_eq!(Ok(1), program.run(builtin));
+
+    for input in [
+        "C200B40A82",
+        "04005AC33890",
+        "880086C3E88112",
+        "CE00C43D881120",
+        "D8005AC2A8F0",
+        "F600BC2D8F",
+        "9C005AC2F8F0",
+    ] {
+        let message = Message::from_str(input).unwrap();
+        assert_eq!(
+            Ok(message.eval()),
+            Program::compile(&message, builtin).unwrap().run(builtin)
+        );
+    }
+
+    let mut rng = Prng::new(28);
+    for _ in 0..FUZZ_ROUNDS {
+        let message = random_message(&mut rng, 4, 1 << 40);
+        assert_eq!(
+            Ok(message.eval()),
+            Program::compile(&message, builtin).unwrap().run(builtin)
+        );
+    }
+
+    let mut deep = Message::Literal(0, 1);
+    for _ in 0..5000 {
+        deep = Message::Sum(0, vec![deep, Message::Literal(0, 1)]);
+    }
+    assert_eq!(
+        Ok(5001),
+        Program::compile(&deep, builtin).unwrap().run(builtin)
+    );
+
+    assert_eq!(
+        Err(Oopsie::UnknownOperator(4)),
+        Program::compile(
+            &Message::Sequence(0, 4, vec![Message::Literal(0, 1)]),
+            builtin
+        )
+    );
+    let underflow = Program {
+        code: vec![Instruction::Push(1), Instruction::Apply(6, 2)],
+    };
+    assert_eq!(Err(Oopsie::StackUnderflow(1)), underflow.run(builtin));
+    let empty_minimum = Program {
+        code: vec![Instruction::Apply(2, 0)],
+    };
+    assert_eq!(
+        Err(Oopsie::UndefinedResult("minimum")),
+        empty_minimum.run(builtin)
+    );
+    let bad_arity = Program {
+        code: vec![Instruction::Push(1), Instruction::Apply(5, 1)],
+    };
+    assert_eq!(
+        Err(Oopsie::BadArity("greater than", 1)),
+        bad_arity.run(builtin)
+    );
+    let leftovers = Program {
+        code: vec![Instruction::Push(1), Instruction::Push(2)],
+    };
+    assert_eq!(Err(Oopsie::LeftoverStack(2)), leftovers.run(builtin));
+
+    let dialect = dialect_registry();
+    let custom = Message::Sequence(
+        0,
+        6,
+        vec![
+            Message::Sequence(0, 2, vec![Message::Literal(0, 6), Message::Literal(0, 3)]),
+            Message::Literal(0, 7),
+            Message::Sequence(0, 3, vec![Message::Literal(0, 7), Message::Literal(0, 4)]),
+        ],
+    );
+    let program = Program::compile(&custom, &dialect).unwrap();
+    assert_eq!(Ok(7), program.run(&dialect));
+    assert_eq!(custom.evaluate(&dialect), program.run(&dialect));
+    assert_eq!(
+        Err(Oopsie::BadArity("less than", 3)),
+        Program::compile(&custom, builtin)
+    );
+    assert_eq!(
+        Err(Oopsie::UndefinedResult("modulo")),
+        Program::compile(
+            &Message::Sequence(0, 3, vec![Message::Literal(0, 1), Message::Literal(0, 0)]),
+            &dialect
+        )
+        .unwrap()
+        .run(&dialect)
+    );
+}
+
+#[cfg(test)]
+const FUZZ_ROUNDS: u64 = 500;
+
+#[cfg(test)]
+fn random_message(rng: &mut Prng, depth: usize, max_value: u64) -> Message {
+    let version = rng.below(8) as u8;
+
+    if depth == 0 || max_value == 0 || rng.below(4) == 0 {
+        return Message::Literal(version, rng.below(max_value.min(1 << 20) + 1));
+    }
+
+    let count = 1 + rng.below(4);
+    let children = |rng: &mut Prng, count: u64, bound: u64| -> Vec<Message> {
+        (0..count)
+            .map(|_| random_message(rng, depth - 1, bound))
+            .collect()
+    };
+
+    match rng.below(7) {
+        0 => Message::Sum(version, children(rng, count, max_value / count)),
+        1 => {
+            let bound = (max_value as f64).powf(1.0 / count as f64) as u64;
+            Message::Product(version, children(rng, count, bound))
+        }
+        2 => Message::Minimum(version, children(rng, count, max_value)),
+        3 => Message::Maximum(version, children(rng, count, max_value)),
+        4 => Message::GreaterThan(version, children(rng, 2, 1 << 20)),
+        5 => Message::LessThan(version, children(rng, 2, 1 << 20)),
+        _ => Message::EqualTo(version, children(rng, 2, 1 << 20)),
+    }
+}
+
+#[test]
+fn fuzz_round_trips() {
+    let mut rng = Prng::new(16);
+
+    for _ in 0..FUZZ_ROUNDS {
+        let message = random_message(&mut rng, 4, 1 << 40);
+        let expected = message.eval();
+
+        for length_type in [LengthType::TotalBits, LengthType::PacketCount] {
+            let encoded = message.encode(length_type).unwrap().to_string();
+            let decoded = Message::from_str(&encoded).unwrap();
+            assert_eq!(message, decoded, "round trip of {}", encoded);
+            assert_eq!(expected, decoded.eval(), "evaluation of {}", encoded);
+        }
+    }
+}
+
+#[test]
+fn fuzz_truncation() {
+    let mut rng = Prng::new(1621);
+
+    for _ in 0..FUZZ_ROUNDS {
+        let message = random_message(&mut rng, 4, 1 << 40);
+        let length_type = if rng.next_bool() {
+            LengthType::TotalBits
+        } else {
+            LengthType::PacketCount
+        };
+        let mut stream = message.encode(length_type).unwrap();
+        let encoded = stream.to_string();
+        let digits = rng.below((stream.len() as u64 - 1) / 4 + 1) as usize;
+        assert!(Message::from_str(&encoded[..digits]).is_err());
+
+        let cut = rng.below(stream.len() as u64) as usize;
+        stream.bits.truncate(cut);
+        assert!(Message::try_from(&mut stream).is_err());
+    }
+}
+
+#[test]
+fn fuzz_bit_flips() {
+    let mut rng = Prng::new(2021);
+
+    for _ in 0..FUZZ_ROUNDS {
+        let message = random_message(&mut rng, 4, 1 << 40);
+        let length_type = if rng.next_bool() {
+            LengthType::TotalBits
+        } else {
+            LengthType::PacketCount
+        };
+        let mut stream = message.encode(length_type).unwrap();
+
+        for _ in 0..=rng.below(3) {
+            let idx = rng.below(stream.len() as u64) as usize;
+            stream.bits[idx] = !stream.bits[idx];
+        }
+
+        if let Ok(flipped) = Message::try_from(&mut stream) {
+            flipped.version_sum();
+        }
+    }
+
+    for _ in 0..FUZZ_ROUNDS {
+        let garbage: String = (0..rng.below(32))
+            .map(|_| char::from(32 + rng.below(95) as u8))
+            .collect();
+        let _ = Message::from_str(&garbage);
+    }
+}
+
 fn main() -> Result<(), Oopsie> {
     let message = Message::from_str(REAL_DATA)?;
     println!("Version sum: {}", message.version_sum());
     println!("Input computed value: {}", message.eval());
+    let optimized = message.optimize();
+    println!("Optimized computed value: {}", optimized.eval());
+    for length_type in [LengthType::TotalBits, LengthType::PacketCount] {
+        println!(
+            "Optimization ({:?}): {}",
+            length_type,
+            OptimizationReport::new(&message, &optimized, length_type)?
+        );
+    }
+
+    let builtin = OperatorRegistry::builtins();
+    let program = Program::compile(&message, builtin)?;
+    println!("Bytecode computed value: {}", program.run(builtin)?);
+
+    let start = Instant::now();
+    for _ in 0..BENCHMARK_ROUNDS {
+        black_box(message.eval());
+    }
+    let tree_time = start.elapsed();
+    let start = Instant::now();
+    for _ in 0..BENCHMARK_ROUNDS {
+        black_box(program.run(builtin)?);
+    }
+    let bytecode_time = start.elapsed();
+    println!(
+        "{} evaluations: tree walk {:?}, bytecode {:?}",
+        BENCHMARK_ROUNDS, tree_time, bytecode_time
+    );
+
     Ok(())
 }
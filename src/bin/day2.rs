@@ -1,4 +1,6 @@
 use advent2021::from_file_data;
+use advent2021::submarine::{self, Basic, Command, Submarine, WithAim};
+use std::collections::{HashMap, HashSet};
 use std::num;
 use std::str::FromStr;
 use thiserror::Error;
@@ -7,121 +9,221 @@ use thiserror::Error;
 const TEST_DATA: &str = include_str!("../../data/day2_test.txt");
 const DAY2A: &str = include_str!("../../data/day2a.txt");
 
+const MANEUVER: &str = "\
+# sweep out and back along a shelf
+macro dive {
+    down 3
+    forward 2 # settle in
+}
+
+repeat 3 {
+    dive
+    forward 4
+}
+turn
+back 2
+set aim 3
+up 2
+down 2
+forward 2
+";
+
 #[derive(Debug, Error, PartialEq)]
 enum Oopsie {
-    #[error("Couldn't understand '{0}' as a command.")]
-    CouldntParseCommand(String),
+    #[error("{0}")]
+    BadCommand(#[from] submarine::Oopsie),
     #[error("Couldn't parse number: {0}")]
     CouldntParseNumber(#[from] num::ParseIntError),
+    #[error("Line {0}: {1}")]
+    AtLine(usize, Box<Oopsie>),
+    #[error("Block opened here is never closed")]
+    UnclosedBlock,
+    #[error("Closing brace without a block to close")]
+    UnexpectedClose,
+    #[error("Macros can only be defined at the top level")]
+    NestedMacro,
+    #[error("Bad macro name '{0}'")]
+    BadMacroName(String),
+    #[error("Macro '{0}' is defined twice")]
+    DuplicateMacro(String),
+    #[error("No macro named '{0}'")]
+    UnknownMacro(String),
+    #[error("Macro '{0}' ends up calling itself")]
+    RecursiveMacro(String),
 }
 
-#[derive(Debug, Eq, PartialEq)]
-enum Command {
-    Forward(isize),
-    Down(isize),
-    Up(isize),
-}
-
-impl FromStr for Command {
-    type Err = Oopsie;
+const KEYWORDS: [&str; 9] = [
+    "forward", "down", "up", "back", "turn", "set", "aim", "repeat", "macro",
+];
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if let Some(rest) = s.strip_prefix("forward ") {
-            Ok(Command::Forward(isize::from_str(rest)?))
-        } else if let Some(rest) = s.strip_prefix("down ") {
-            Ok(Command::Down(isize::from_str(rest)?))
-        } else if let Some(rest) = s.strip_prefix("up ") {
-            Ok(Command::Up(isize::from_str(rest)?))
-        } else {
-            Err(Oopsie::CouldntParseCommand(s.to_string()))
-        }
-    }
+#[derive(Debug, PartialEq)]
+enum Statement {
+    Do(Command),
+    Repeat(usize, Vec<Statement>),
+    // the line number is kept around for error reporting
+    Call(String, usize),
 }
 
-#[test]
-fn command_parsing() {
-    assert_eq!(Ok(Command::Forward(5)), Command::from_str("forward 5"));
-    assert_eq!(Ok(Command::Down(5)), Command::from_str("down 5"));
-    assert_eq!(Ok(Command::Forward(8)), Command::from_str("forward 8"));
-    assert_eq!(Ok(Command::Up(3)), Command::from_str("up 3"));
-    assert_eq!(Ok(Command::Down(8)), Command::from_str("down 8"));
-    assert_eq!(Ok(Command::Forward(2)), Command::from_str("forward 2"));
+#[derive(Debug, Default, PartialEq)]
+struct Program {
+    macros: HashMap<String, Vec<Statement>>,
+    body: Vec<Statement>,
 }
 
-#[test]
-fn file_parsing() {
-    let result: Result<Vec<Command>, Oopsie> = from_file_data(TEST_DATA);
-    assert!(result.is_ok());
+enum Block {
+    Top,
+    Repeat(usize),
+    Macro(String),
 }
 
-#[derive(Debug)]
-struct Submarine {
-    interpretation: Interpretation,
-    depth: isize,
-    position_x: isize,
-    aim: isize,
+fn at_line(line: usize) -> impl Fn(Oopsie) -> Oopsie {
+    move |e| Oopsie::AtLine(line, Box::new(e))
 }
 
-#[derive(Debug)]
-enum Interpretation {
-    Basic,
-    WithAim,
+fn valid_macro_name(name: &str) -> bool {
+    name.chars().next().is_some_and(|c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
 }
 
-impl Submarine {
-    fn new(interpretation: Interpretation) -> Submarine {
-        Submarine {
-            interpretation,
-            depth: 0,
-            position_x: 0,
-            aim: 0,
-        }
-    }
+impl FromStr for Program {
+    type Err = Oopsie;
 
-    fn command(&mut self, cmd: &Command) {
-        match self.interpretation {
-            Interpretation::Basic => match cmd {
-                Command::Forward(v) => self.position_x += v,
-                Command::Up(v) => self.depth -= v,
-                Command::Down(v) => self.depth += v,
-            },
-
-            Interpretation::WithAim => match cmd {
-                Command::Forward(v) => {
-                    self.position_x += v;
-                    self.depth += self.aim * v;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut program = Program::default();
+        // each open block, the line it was opened on, and what's in it so far
+        let mut stack = vec![(Block::Top, 0, Vec::new())];
+
+        for (idx, raw) in s.lines().enumerate() {
+            let line_no = idx + 1;
+            let line = raw.split('#').next().unwrap_or_default().trim();
+            let words: Vec<&str> = line.split_whitespace().collect();
+
+            match words[..] {
+                [] => {}
+                ["}"] => {
+                    if stack.len() == 1 {
+                        return Err(at_line(line_no)(Oopsie::UnexpectedClose));
+                    }
+                    let (block, _, statements) = stack.pop().unwrap();
+                    match block {
+                        Block::Top => unreachable!(),
+                        Block::Repeat(count) => {
+                            let parent = &mut stack.last_mut().unwrap().2;
+                            parent.push(Statement::Repeat(count, statements));
+                        }
+                        Block::Macro(name) => {
+                            program.macros.insert(name, statements);
+                        }
+                    }
+                }
+                ["repeat", count, "{"] => {
+                    let count = usize::from_str(count).map_err(|e| at_line(line_no)(e.into()))?;
+                    stack.push((Block::Repeat(count), line_no, Vec::new()));
+                }
+                ["macro", name, "{"] => {
+                    if stack.len() != 1 {
+                        return Err(at_line(line_no)(Oopsie::NestedMacro));
+                    }
+                    if !valid_macro_name(name) {
+                        return Err(at_line(line_no)(Oopsie::BadMacroName(name.to_string())));
+                    }
+                    if program.macros.contains_key(name) {
+                        return Err(at_line(line_no)(Oopsie::DuplicateMacro(name.to_string())));
+                    }
+                    stack.push((Block::Macro(name.to_string()), line_no, Vec::new()));
                 }
-                Command::Up(v) => self.aim -= v,
-                Command::Down(v) => self.aim += v,
-            },
+                [name] if valid_macro_name(name) => {
+                    let current = &mut stack.last_mut().unwrap().2;
+                    current.push(Statement::Call(name.to_string(), line_no));
+                }
+                _ => {
+                    let command =
+                        Command::from_str(line).map_err(|e| at_line(line_no)(e.into()))?;
+                    stack.last_mut().unwrap().2.push(Statement::Do(command));
+                }
+            }
+        }
+
+        let (_, opened, body) = stack.pop().unwrap();
+        if !stack.is_empty() {
+            return Err(at_line(opened)(Oopsie::UnclosedBlock));
         }
+        program.body = body;
+        program.check_calls()?;
+
+        Ok(program)
     }
+}
 
-    fn run(&mut self, cmds: &[Command]) {
-        for cmd in cmds.iter() {
-            self.command(cmd);
+impl Program {
+    // every call has to name a macro, and no macro can end up calling itself;
+    // each macro gets checked exactly once, whether or not anything calls it
+    fn check_calls(&self) -> Result<(), Oopsie> {
+        let mut done = HashSet::new();
+        self.check_statements(&self.body, &mut Vec::new(), &mut done)?;
+
+        let mut names: Vec<&String> = self.macros.keys().collect();
+        names.sort_unstable();
+        for name in names {
+            if !done.contains(name.as_str()) {
+                self.check_statements(&self.macros[name], &mut vec![name], &mut done)?;
+                done.insert(name);
+            }
         }
+
+        Ok(())
     }
 
-    fn distance(&self) -> isize {
-        self.depth * self.position_x
+    fn check_statements<'a>(
+        &'a self,
+        statements: &'a [Statement],
+        active: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Result<(), Oopsie> {
+        for statement in statements.iter() {
+            match statement {
+                Statement::Do(_) => {}
+                Statement::Repeat(_, inner) => self.check_statements(inner, active, done)?,
+                Statement::Call(name, line_no) => {
+                    if active.contains(&name.as_str()) {
+                        return Err(at_line(*line_no)(Oopsie::RecursiveMacro(name.clone())));
+                    }
+                    if done.contains(name.as_str()) {
+                        continue;
+                    }
+                    let inner = self
+                        .macros
+                        .get(name)
+                        .ok_or_else(|| at_line(*line_no)(Oopsie::UnknownMacro(name.clone())))?;
+                    active.push(name);
+                    self.check_statements(inner, active, done)?;
+                    active.pop();
+                    done.insert(name);
+                }
+            }
+        }
+
+        Ok(())
     }
-}
 
-#[test]
-fn basic_test_intepretation() {
-    let test_commands = from_file_data(TEST_DATA).unwrap();
-    let mut test_submarine = Submarine::new(Interpretation::Basic);
-    test_submarine.run(&test_commands);
-    assert_eq!(150, test_submarine.distance());
-}
+    fn run(&self, submarine: &mut Submarine) {
+        self.execute(&self.body, submarine);
+    }
 
-#[test]
-fn test_intepretation_with_aim() {
-    let test_commands = from_file_data(TEST_DATA).unwrap();
-    let mut test_submarine = Submarine::new(Interpretation::WithAim);
-    test_submarine.run(&test_commands);
-    assert_eq!(900, test_submarine.distance());
+    fn execute(&self, statements: &[Statement], submarine: &mut Submarine) {
+        for statement in statements.iter() {
+            match statement {
+                Statement::Do(cmd) => submarine.command(cmd),
+                Statement::Repeat(count, inner) => {
+                    for _ in 0..*count {
+                        self.execute(inner, submarine);
+                    }
+                }
+                Statement::Call(name, _) => self.execute(&self.macros[name], submarine),
+            }
+        }
+    }
 }
 
 // just in case I decide to be fancy and pull some stuff out of this for a
@@ -130,26 +232,204 @@ fn test_intepretation_with_aim() {
 fn regression_tests() {
     let commands = from_file_data(DAY2A).unwrap();
 
-    let mut part1_submarine = Submarine::new(Interpretation::Basic);
+    let mut part1_submarine = Submarine::new(Basic);
     part1_submarine.run(&commands);
     assert_eq!(2039912, part1_submarine.distance());
 
-    let mut part2_submarine = Submarine::new(Interpretation::WithAim);
+    let mut part2_submarine = Submarine::new(WithAim);
     part2_submarine.run(&commands);
     assert_eq!(1942068080, part2_submarine.distance());
 }
 
+#[test]
+fn programs() {
+    // the plain puzzle input is already a valid program
+    let program = Program::from_str(DAY2A).unwrap();
+    let mut submarine = Submarine::new(WithAim);
+    program.run(&mut submarine);
+    assert_eq!(1942068080, submarine.distance());
+
+    let program = Program::from_str(MANEUVER).unwrap();
+    let mut basic = Submarine::new(Basic);
+    program.run(&mut basic);
+    assert_eq!((18, 9), (basic.state.position_x, basic.state.depth));
+    let mut with_aim = Submarine::new(WithAim);
+    program.run(&mut with_aim);
+    assert_eq!(
+        (18, 96, 3),
+        (
+            with_aim.state.position_x,
+            with_aim.state.depth,
+            with_aim.state.aim
+        )
+    );
+
+    let error = |text: &str| Program::from_str(text).unwrap_err();
+    assert_eq!(
+        Oopsie::AtLine(
+            2,
+            Box::new(Oopsie::BadCommand(submarine::Oopsie::CouldntParseCommand(
+                "sideways 3".to_string()
+            )))
+        ),
+        error("forward 1\nsideways 3 # oops\n")
+    );
+    assert_eq!(
+        Oopsie::AtLine(1, Box::new(Oopsie::UnclosedBlock)),
+        error("repeat 2 {\n  forward 1\n")
+    );
+    assert_eq!(
+        Oopsie::AtLine(2, Box::new(Oopsie::UnexpectedClose)),
+        error("forward 1\n}\n")
+    );
+    assert_eq!(
+        Oopsie::AtLine(2, Box::new(Oopsie::NestedMacro)),
+        error("repeat 2 {\n  macro m {\n  }\n}\n")
+    );
+    assert_eq!(
+        Oopsie::AtLine(3, Box::new(Oopsie::UnknownMacro("dive".to_string()))),
+        error("forward 1\n\ndive\n")
+    );
+    assert_eq!(
+        Oopsie::AtLine(5, Box::new(Oopsie::RecursiveMacro("a".to_string()))),
+        error("macro a {\n  b\n}\nmacro b {\n  a\n}\na\n")
+    );
+    // macros are checked even when nothing calls them
+    assert_eq!(
+        Oopsie::AtLine(2, Box::new(Oopsie::UnknownMacro("nope".to_string()))),
+        error("macro unused {\n  nope\n}\nforward 1\n")
+    );
+    assert_eq!(
+        Oopsie::AtLine(2, Box::new(Oopsie::RecursiveMacro("a".to_string()))),
+        error("macro a {\n  a\n}\n")
+    );
+    // each macro doubles the last one, so walking every call site would
+    // take 2^40 steps
+    let mut chain = String::from("macro m40 {\n  forward 1\n}\n");
+    for i in (0..40).rev() {
+        chain += &format!("macro m{} {{\n  m{}\n  m{}\n}}\n", i, i + 1, i + 1);
+    }
+    chain += "m0\n";
+    assert!(Program::from_str(&chain).is_ok());
+    assert_eq!(
+        Oopsie::AtLine(1, Box::new(Oopsie::BadMacroName("turn".to_string()))),
+        error("macro turn {\n}\n")
+    );
+    assert!(matches!(error("repeat many {\n}\n"), Oopsie::AtLine(1, _)));
+    assert_eq!(
+        "Line 2: Couldn't understand 'up' as a command.",
+        error("down 1\nup\n").to_string()
+    );
+}
+
+#[test]
+fn traces() {
+    let commands: Vec<Command> = from_file_data(TEST_DATA).unwrap();
+    let mut submarine = Submarine::new(WithAim).with_trace();
+    submarine.run(&commands);
+    let trace = submarine.trace.as_ref().unwrap();
+    assert_eq!(6, trace.steps.len());
+    assert_eq!((6, 60), trace.max_depth());
+    assert_eq!(Some((3, Command::Forward(8))), trace.first_crossing(1));
+    assert_eq!(None, trace.first_crossing(100));
+    assert!(trace.above_surface().is_empty());
+    let csv = trace.to_csv();
+    assert_eq!(
+        vec![
+            "step,command,x,depth,aim",
+            "0,start,0,0,0",
+            "1,forward 5,5,0,0",
+            "2,down 5,5,0,5",
+        ],
+        csv.lines().take(4).collect::<Vec<_>>()
+    );
+
+    let program = Program::from_str("down 2\nup 5\nforward 1\ndown 6\nup 4\n").unwrap();
+    let mut submarine = Submarine::new(Basic).with_trace();
+    program.run(&mut submarine);
+    let trace = submarine.trace.as_ref().unwrap();
+    assert_eq!(vec![2..4, 5..6], trace.above_surface());
+    assert_eq!(Some((4, Command::Down(6))), trace.first_crossing(3));
+    assert_eq!(
+        vec!["*****", "*   E", "S~~~*", "*   *", "    *"],
+        trace.ascii_plot(5, 5).lines().collect::<Vec<_>>()
+    );
+    assert_eq!("", trace.ascii_plot(0, 5));
+    assert_eq!("", trace.ascii_plot(5, 0));
+    assert_eq!("E\n", trace.ascii_plot(1, 1));
+    let svg = trace.svg(100, 50);
+    assert!(svg.contains("viewBox=\"0 -3 1 6\""));
+    assert!(svg.contains("points=\"0,0 0,2 0,-3 1,-3 1,3 1,-1\""));
+
+    let mut untraced = Submarine::new(Basic);
+    untraced.run(&commands);
+    assert!(untraced.trace.is_none());
+}
+
+#[test]
+fn outside_interpretation() {
+    use advent2021::submarine::{check_interpretation, Interpretation, State};
+
+    // a sub with its dive planes wired backwards
+    #[derive(Debug)]
+    struct Inverted;
+
+    impl Interpretation for Inverted {
+        fn apply(&self, state: &mut State, cmd: &Command) {
+            match cmd {
+                Command::Up(v) => Basic.apply(state, &Command::Down(*v)),
+                Command::Down(v) => Basic.apply(state, &Command::Up(*v)),
+                other => Basic.apply(state, other),
+            }
+        }
+    }
+
+    assert_eq!(
+        Ok(()),
+        check_interpretation(|| Inverted, &[(15, -10), (6, -2), (4, 2), (4, 0)])
+    );
+    assert_eq!(
+        "Fixture 'example' under Inverted ended at (15, -10), not (15, 10)",
+        check_interpretation(|| Inverted, &[(15, 10), (6, -2), (4, 2), (4, 0)])
+            .unwrap_err()
+            .to_string()
+    );
+}
+
 fn day2() -> Result<(), Oopsie> {
     let commands = from_file_data(DAY2A)?;
 
-    let mut part1_submarine = Submarine::new(Interpretation::Basic);
+    let mut part1_submarine = Submarine::new(Basic);
     part1_submarine.run(&commands);
     println!("Part 1 distance: {}", part1_submarine.distance());
 
-    let mut part2_submarine = Submarine::new(Interpretation::WithAim);
+    let mut part2_submarine = Submarine::new(WithAim);
     part2_submarine.run(&commands);
     println!("Part 2 distance: {}", part2_submarine.distance());
 
+    let maneuver = Program::from_str(MANEUVER)?;
+    for submarine in [Submarine::new(Basic), Submarine::new(WithAim)] {
+        let mut submarine = submarine.with_trace();
+        maneuver.run(&mut submarine);
+        println!(
+            "Maneuver ends at x={}, depth={}, aim={} ({:?})",
+            submarine.state.position_x,
+            submarine.state.depth,
+            submarine.state.aim,
+            submarine.interpretation
+        );
+
+        if let Some(trace) = submarine.trace.as_ref() {
+            let (step, depth) = trace.max_depth();
+            println!("  deepest point: {} at step {}", depth, step);
+            println!("  first past 20: {:?}", trace.first_crossing(20));
+            println!("  above the surface: {:?}", trace.above_surface());
+            print!("{}", trace.ascii_plot(40, 8));
+            print!("{}", trace.to_csv());
+            print!("{}", trace.svg(400, 200));
+        }
+    }
+
     Ok(())
 }
 
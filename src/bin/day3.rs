@@ -1,6 +1,10 @@
-use advent2021::from_file_data;
+#[cfg(test)]
+use advent2021::prng::Prng;
+use std::cmp::Ordering;
 use std::fmt::{self, Write};
 use std::str::FromStr;
+#[cfg(test)]
+use std::time::Instant;
 use thiserror::Error;
 
 #[cfg(test)]
@@ -11,35 +15,126 @@ const DAY3A_DATA: &str = include_str!("../../data/day3a.txt");
 enum Oopsie {
     #[error("Invalid bit found in number: {0}")]
     InvalidBit(char),
-    #[error("Indeterminate value in filter; ran off the end")]
-    IndeterminateValue,
+    #[error("Column {column} is tied, with {ones} ones out of {total}")]
+    TiedColumn {
+        column: usize,
+        ones: usize,
+        total: usize,
+    },
+    #[error("Ran out of columns with {0} identical candidates left")]
+    DuplicateCandidates(usize),
+    #[error("No candidates to filter")]
+    NoCandidates,
+    #[error("Line {0} has {1} bits, but earlier lines have {2}")]
+    InconsistentWidth(usize, usize, usize),
+    #[error("Value is {0} bits wide, which doesn't fit")]
+    TooWide(usize),
+}
+
+const WORD_BITS: usize = u64::BITS as usize;
+
+// which bit wins when a column has as many ones as zeros
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum TiePolicy {
+    PreferOne,
+    PreferZero,
+    Error,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Criterion {
+    MostCommon,
+    LeastCommon,
+}
+
+impl Criterion {
+    fn pick(
+        &self,
+        column: usize,
+        ones: usize,
+        total: usize,
+        ties: TiePolicy,
+    ) -> Result<bool, Oopsie> {
+        match ((2 * ones).cmp(&total), ties) {
+            (Ordering::Greater, _) => Ok(*self == Criterion::MostCommon),
+            (Ordering::Less, _) => Ok(*self == Criterion::LeastCommon),
+            (Ordering::Equal, TiePolicy::PreferOne) => Ok(true),
+            (Ordering::Equal, TiePolicy::PreferZero) => Ok(false),
+            (Ordering::Equal, TiePolicy::Error) => Err(Oopsie::TiedColumn {
+                column,
+                ones,
+                total,
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct RatingStep {
+    column: usize,
+    ones: usize,
+    zeros: usize,
+    kept: bool,
+    survivors: Vec<usize>,
+}
+
+impl fmt::Display for RatingStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "column {}: {} ones, {} zeros, kept {}s; lines {:?} survive",
+            self.column, self.ones, self.zeros, self.kept as u8, self.survivors
+        )
+    }
+}
+
+// bit i of a value lives in word i / 64, at position i % 64, with bit 0
+// being the leftmost character of the input
+fn get_bit(words: &[u64], i: usize) -> bool {
+    words[i / WORD_BITS] & (1 << (i % WORD_BITS)) != 0
+}
+
+fn set_bit(words: &mut [u64], i: usize) {
+    words[i / WORD_BITS] |= 1 << (i % WORD_BITS);
 }
 
 #[derive(Clone, Debug, PartialEq)]
-struct Datum(Vec<bool>);
+struct Datum {
+    width: usize,
+    words: Vec<u64>,
+}
+
+impl Datum {
+    fn zeros(width: usize) -> Datum {
+        Datum {
+            width,
+            words: vec![0; width.div_ceil(WORD_BITS)],
+        }
+    }
+}
 
 impl FromStr for Datum {
     type Err = Oopsie;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut retval = Vec::with_capacity(s.len());
+        let mut retval = Datum::zeros(s.len());
 
-        for c in s.chars() {
+        for (i, c) in s.chars().enumerate() {
             match c {
-                '0' => retval.push(false),
-                '1' => retval.push(true),
+                '0' => {}
+                '1' => set_bit(&mut retval.words, i),
                 bad => return Err(Oopsie::InvalidBit(bad)),
             }
         }
 
-        Ok(Datum(retval))
+        Ok(retval)
     }
 }
 
 impl fmt::Display for Datum {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for v in self.0.iter() {
-            if *v {
+        for i in 0..self.width {
+            if get_bit(&self.words, i) {
                 f.write_char('1')?
             } else {
                 f.write_char('0')?
@@ -49,132 +144,262 @@ impl fmt::Display for Datum {
     }
 }
 
-impl From<Datum> for usize {
-    fn from(x: Datum) -> Self {
-        let mut retval = 0;
+impl TryFrom<&Datum> for u128 {
+    type Error = Oopsie;
 
-        for b in x.0.iter() {
+    fn try_from(x: &Datum) -> Result<Self, Self::Error> {
+        if x.width > u128::BITS as usize {
+            return Err(Oopsie::TooWide(x.width));
+        }
+
+        let mut retval = 0;
+        for i in 0..x.width {
             retval <<= 1;
-            if *b {
+            if get_bit(&x.words, i) {
                 retval += 1;
             }
         }
 
-        retval
+        Ok(retval)
+    }
+}
+
+impl TryFrom<&Datum> for usize {
+    type Error = Oopsie;
+
+    fn try_from(x: &Datum) -> Result<Self, Self::Error> {
+        if x.width > usize::BITS as usize {
+            return Err(Oopsie::TooWide(x.width));
+        }
+
+        Ok(u128::try_from(x)? as usize)
     }
 }
 
 #[test]
 fn can_read_diagnostic_data_bits() {
-    assert_eq!(
-        Ok(Datum(vec![false, false, true, false, false])),
-        Datum::from_str("00100")
-    );
-    assert_eq!(
-        Ok(Datum(vec![true, true, true, true, false])),
-        Datum::from_str("11110")
-    );
+    let datum = Datum::from_str("00100").unwrap();
+    assert_eq!(5, datum.width);
+    assert_eq!(vec![0b00100], datum.words);
+    assert_eq!("00100", datum.to_string());
+    assert_eq!(Ok(4), usize::try_from(&datum));
+    let datum = Datum::from_str("11110").unwrap();
+    assert_eq!(vec![0b01111], datum.words);
+    assert_eq!(Ok(30), usize::try_from(&datum));
+    assert_eq!(Err(Oopsie::InvalidBit('2')), Datum::from_str("01210"));
 }
 
-#[derive(Debug, PartialEq)]
-struct Diagnostics(Vec<Datum>);
-
-impl<'a> From<&'a [Datum]> for Diagnostics {
-    fn from(data: &'a [Datum]) -> Self {
-        Diagnostics(data.to_vec())
-    }
+// every row is packed into the same number of words, back to back
+#[derive(Clone, Debug, PartialEq)]
+struct Diagnostics {
+    width: usize,
+    stride: usize,
+    rows: usize,
+    words: Vec<u64>,
 }
 
 impl FromStr for Diagnostics {
     type Err = Oopsie;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Diagnostics(from_file_data(s)?))
+        let mut lines = s.lines().peekable();
+        let width = lines.peek().map(|x| x.len()).unwrap_or_default();
+        let stride = width.div_ceil(WORD_BITS);
+        let mut retval = Diagnostics {
+            width,
+            stride,
+            rows: 0,
+            words: Vec::new(),
+        };
+
+        for (line_no, line) in lines.enumerate() {
+            if line.len() != width {
+                return Err(Oopsie::InconsistentWidth(line_no + 1, line.len(), width));
+            }
+
+            let start = retval.words.len();
+            retval.words.resize(start + stride, 0);
+            for (i, c) in line.chars().enumerate() {
+                match c {
+                    '0' => {}
+                    '1' => set_bit(&mut retval.words[start..], i),
+                    bad => return Err(Oopsie::InvalidBit(bad)),
+                }
+            }
+            retval.rows += 1;
+        }
+
+        Ok(retval)
     }
 }
 
 impl Diagnostics {
-    fn most_common_bits(&self) -> Vec<bool> {
-        let total_items = self.0.len();
-        let entries_per_datum = self.0.iter().map(|x| x.0.len()).min().unwrap();
-        let initial_values = vec![0; entries_per_datum];
-        let true_counts = self.0.iter().fold(initial_values, |acc, x| {
-            acc.iter()
-                .zip(x.0.iter())
-                .map(|(count, val)| if *val { count + 1 } else { *count })
-                .collect()
-        });
-        true_counts
-            .iter()
-            .map(|count| *count >= ((total_items + 1) / 2))
-            .collect()
+    fn row(&self, row: usize) -> &[u64] {
+        &self.words[row * self.stride..(row + 1) * self.stride]
     }
 
-    fn get_rates(&self) -> (usize, usize) {
-        self.most_common_bits()
-            .iter()
-            .fold((0, 0), |(gacc, eacc), most_common_is_one| {
-                let mut new_gamma = gacc << 1;
-                let mut new_epsilon = eacc << 1;
-
-                if *most_common_is_one {
-                    new_gamma += 1;
-                } else {
-                    new_epsilon += 1;
+    fn datum(&self, row: usize) -> Datum {
+        Datum {
+            width: self.width,
+            words: self.row(row).to_vec(),
+        }
+    }
+
+    fn column_counts(&self) -> Vec<usize> {
+        let mut counts = vec![0; self.stride * WORD_BITS];
+
+        for row in self.words.chunks(self.stride.max(1)) {
+            for (idx, word) in row.iter().enumerate() {
+                let mut word = *word;
+                while word != 0 {
+                    counts[idx * WORD_BITS + word.trailing_zeros() as usize] += 1;
+                    word &= word - 1;
+                }
+            }
+        }
+
+        counts.truncate(self.width);
+        counts
+    }
+
+    fn column_count(&self, column: usize) -> usize {
+        (0..self.rows)
+            .filter(|row| get_bit(self.row(*row), column))
+            .count()
+    }
+
+    // compacts the surviving rows towards the front, keeping their order
+    fn retain_rows<F: FnMut(usize, &[u64]) -> bool>(&mut self, mut keep: F) {
+        let mut kept = 0;
+
+        for row in 0..self.rows {
+            if keep(row, self.row(row)) {
+                if kept != row {
+                    self.words.copy_within(
+                        row * self.stride..(row + 1) * self.stride,
+                        kept * self.stride,
+                    );
                 }
+                kept += 1;
+            }
+        }
+
+        self.rows = kept;
+        self.words.truncate(kept * self.stride);
+    }
+
+    fn rates(
+        &self,
+        gamma_ties: TiePolicy,
+        epsilon_ties: TiePolicy,
+    ) -> Result<(Datum, Datum), Oopsie> {
+        let mut gamma = Datum::zeros(self.width);
+        let mut epsilon = Datum::zeros(self.width);
+
+        for (column, ones) in self.column_counts().into_iter().enumerate() {
+            if Criterion::MostCommon.pick(column, ones, self.rows, gamma_ties)? {
+                set_bit(&mut gamma.words, column);
+            }
+            if Criterion::LeastCommon.pick(column, ones, self.rows, epsilon_ties)? {
+                set_bit(&mut epsilon.words, column);
+            }
+        }
 
-                (new_gamma, new_epsilon)
-            })
+        Ok((gamma, epsilon))
     }
 
-    fn rating_generator<F: Fn(bool, bool) -> bool>(&self, f: F) -> Result<usize, Oopsie> {
-        let mut candidates = self.0.clone();
-        let mut offset = 0;
+    fn get_rates(&self) -> Result<(usize, usize), Oopsie> {
+        let (gamma, epsilon) = self.rates(TiePolicy::PreferOne, TiePolicy::PreferZero)?;
+        Ok((usize::try_from(&gamma)?, usize::try_from(&epsilon)?))
+    }
 
-        while candidates.len() > 1 {
-            let most_commons = Diagnostics::from(&candidates[..]).most_common_bits();
-            let mut die_horribly = false;
+    // survivors are only tracked (as 1-based line numbers) when there's a
+    // trace to record them in, so untraced ratings stay cheap on big inputs
+    fn rating_generator(
+        &self,
+        criterion: Criterion,
+        ties: TiePolicy,
+        mut trace: Option<&mut Vec<RatingStep>>,
+    ) -> Result<Datum, Oopsie> {
+        let mut candidates = self.clone();
+        let tracing = trace.is_some();
+        let mut lines: Vec<usize> = match tracing {
+            true => (1..=self.rows).collect(),
+            false => Vec::new(),
+        };
+        let mut column = 0;
+
+        while candidates.rows > 1 {
+            if column >= self.width {
+                return Err(Oopsie::DuplicateCandidates(candidates.rows));
+            }
 
-            candidates.retain(|datum| match datum.0.get(offset) {
-                None => {
-                    die_horribly = true;
-                    false
+            let total = candidates.rows;
+            let ones = candidates.column_count(column);
+            // when every candidate agrees there's nothing to filter on, and
+            // keeping the least common bit would throw them all away
+            let kept = match ones {
+                0 => false,
+                x if x == total => true,
+                _ => criterion.pick(column, ones, total, ties)?,
+            };
+            let mut survivors = Vec::new();
+            candidates.retain_rows(|row, bits| {
+                let keep = get_bit(bits, column) == kept;
+                if keep && tracing {
+                    survivors.push(lines[row]);
                 }
-                Some(current_bit) => match most_commons.get(offset) {
-                    None => {
-                        die_horribly = true;
-                        false
-                    }
-                    Some(most_common_bit) => f(*current_bit, *most_common_bit),
-                },
+                keep
             });
 
-            if die_horribly {
-                return Err(Oopsie::IndeterminateValue);
+            if let Some(trace) = trace.as_deref_mut() {
+                trace.push(RatingStep {
+                    column,
+                    ones,
+                    zeros: total - ones,
+                    kept,
+                    survivors: survivors.clone(),
+                });
+                lines = survivors;
             }
-
-            offset += 1;
+            column += 1;
         }
 
-        match candidates.pop() {
-            None => Err(Oopsie::IndeterminateValue),
-            Some(x) => Ok(usize::from(x)),
+        match candidates.rows {
+            0 => Err(Oopsie::NoCandidates),
+            _ => Ok(candidates.datum(0)),
         }
     }
 
+    fn rating(&self, criterion: Criterion, ties: TiePolicy) -> Result<Datum, Oopsie> {
+        self.rating_generator(criterion, ties, None)
+    }
+
+    fn rating_trace(
+        &self,
+        criterion: Criterion,
+        ties: TiePolicy,
+    ) -> (Vec<RatingStep>, Result<Datum, Oopsie>) {
+        let mut trace = Vec::new();
+        let result = self.rating_generator(criterion, ties, Some(&mut trace));
+        (trace, result)
+    }
+
     fn o2_generator_rating(&self) -> Result<usize, Oopsie> {
-        self.rating_generator(|current, most_common| current == most_common)
+        let rating = self.rating(Criterion::MostCommon, TiePolicy::PreferOne)?;
+        usize::try_from(&rating)
     }
 
     fn co2_scrubber_rating(&self) -> Result<usize, Oopsie> {
-        self.rating_generator(|current, most_common| current != most_common)
+        let rating = self.rating(Criterion::LeastCommon, TiePolicy::PreferZero)?;
+        usize::try_from(&rating)
     }
 }
 
 #[test]
 fn part1_example_works() {
     let diags = Diagnostics::from_str(TEST_DATA).unwrap();
-    let (gamma_rate, epsilon_rate) = diags.get_rates();
+    let (gamma_rate, epsilon_rate) = diags.get_rates().unwrap();
     assert_eq!(22, gamma_rate);
     assert_eq!(9, epsilon_rate);
 }
@@ -188,20 +413,189 @@ fn part2_example_works() {
     assert_eq!(Ok(10), co2r);
 }
 
+#[test]
+fn wide_values() {
+    let wide = format!(
+        "1{}\n1{}1\n0{}\n",
+        "0".repeat(69),
+        "0".repeat(68),
+        "1".repeat(69)
+    );
+    let diags = Diagnostics::from_str(&wide).unwrap();
+    assert_eq!((70, 2, 3), (diags.width, diags.stride, diags.rows));
+    assert_eq!(2, diags.column_count(0));
+    assert_eq!(2, diags.column_counts()[69]);
+
+    let (gamma, epsilon) = diags
+        .rates(TiePolicy::PreferOne, TiePolicy::PreferZero)
+        .unwrap();
+    assert_eq!(Ok((1 << 69) | 1), u128::try_from(&gamma));
+    assert_eq!(Err(Oopsie::TooWide(70)), usize::try_from(&gamma));
+    assert_eq!(format!("0{}0", "1".repeat(68)), epsilon.to_string());
+
+    let o2 = diags
+        .rating(Criterion::MostCommon, TiePolicy::PreferOne)
+        .unwrap();
+    assert_eq!(diags.datum(1), o2);
+    let co2 = diags
+        .rating(Criterion::LeastCommon, TiePolicy::PreferZero)
+        .unwrap();
+    assert_eq!(diags.datum(2), co2);
+
+    let mut filtered = diags.clone();
+    filtered.retain_rows(|_, row| get_bit(row, 0));
+    assert_eq!(2, filtered.rows);
+    assert_eq!(diags.datum(1), filtered.datum(1));
+
+    assert_eq!(
+        Err(Oopsie::InconsistentWidth(2, 4, 5)),
+        Diagnostics::from_str("10110\n1011\n")
+    );
+    assert_eq!(
+        Err(Oopsie::DuplicateCandidates(2)),
+        Diagnostics::from_str("101\n101\n")
+            .unwrap()
+            .o2_generator_rating()
+    );
+}
+
+#[test]
+fn tie_policies_and_traces() {
+    let diags = Diagnostics::from_str(TEST_DATA).unwrap();
+    let (gamma, epsilon) = diags.rates(TiePolicy::Error, TiePolicy::Error).unwrap();
+    assert_eq!(
+        ("10110", "01001"),
+        (&*gamma.to_string(), &*epsilon.to_string())
+    );
+
+    let tied = Diagnostics::from_str("10\n01\n11\n").unwrap();
+    let (gamma, epsilon) = tied
+        .rates(TiePolicy::PreferZero, TiePolicy::PreferOne)
+        .unwrap();
+    assert_eq!(("11", "00"), (&*gamma.to_string(), &*epsilon.to_string()));
+    let tied = Diagnostics::from_str("10\n01\n").unwrap();
+    let (gamma, epsilon) = tied
+        .rates(TiePolicy::PreferZero, TiePolicy::PreferOne)
+        .unwrap();
+    assert_eq!(("00", "11"), (&*gamma.to_string(), &*epsilon.to_string()));
+    assert_eq!(
+        Err(Oopsie::TiedColumn {
+            column: 0,
+            ones: 1,
+            total: 2
+        }),
+        tied.rates(TiePolicy::PreferOne, TiePolicy::Error)
+    );
+
+    let (trace, o2) = diags.rating_trace(Criterion::MostCommon, TiePolicy::PreferOne);
+    assert_eq!(Ok(Datum::from_str("10111").unwrap()), o2);
+    assert_eq!(
+        vec![
+            vec![2, 3, 4, 5, 8, 9, 10],
+            vec![3, 4, 5, 9],
+            vec![3, 4, 5],
+            vec![3, 4],
+            vec![4]
+        ],
+        trace
+            .iter()
+            .map(|x| x.survivors.clone())
+            .collect::<Vec<_>>()
+    );
+    assert_eq!((7, 5, true), (trace[0].ones, trace[0].zeros, trace[0].kept));
+
+    let (trace, o2) = diags.rating_trace(Criterion::MostCommon, TiePolicy::Error);
+    assert_eq!(4, trace.len());
+    assert_eq!(
+        Err(Oopsie::TiedColumn {
+            column: 4,
+            ones: 1,
+            total: 2
+        }),
+        o2
+    );
+    let (_, o2) = diags.rating_trace(Criterion::MostCommon, TiePolicy::PreferZero);
+    assert_eq!(Ok(Datum::from_str("10110").unwrap()), o2);
+    assert_eq!(
+        Ok(Datum::from_str("01010").unwrap()),
+        diags.rating(Criterion::LeastCommon, TiePolicy::PreferZero)
+    );
+    let unanimous = Diagnostics::from_str("010\n011\n000\n").unwrap();
+    let (trace, co2) = unanimous.rating_trace(Criterion::LeastCommon, TiePolicy::Error);
+    assert_eq!(Ok(Datum::from_str("000").unwrap()), co2);
+    assert_eq!(vec![1, 2, 3], trace[0].survivors);
+    assert_eq!(
+        (0, 3, false),
+        (trace[0].ones, trace[0].zeros, trace[0].kept)
+    );
+    assert_eq!(
+        Err(Oopsie::NoCandidates),
+        Diagnostics::from_str("").unwrap().o2_generator_rating()
+    );
+}
+
 #[test]
 fn regression_tests() {
     let diagnostics = Diagnostics::from_str(DAY3A_DATA).unwrap();
-    let (p1gamma, p1epsilon) = diagnostics.get_rates();
+    let (p1gamma, p1epsilon) = diagnostics.get_rates().unwrap();
     assert_eq!(1491, p1gamma);
     assert_eq!(2604, p1epsilon);
     assert_eq!(Ok(1305), diagnostics.o2_generator_rating());
     assert_eq!(Ok(2594), diagnostics.co2_scrubber_rating());
 }
 
+#[cfg(test)]
+fn synthetic(rows: usize, width: usize, seed: u64) -> String {
+    let mut rng = Prng::new(seed);
+    let mut retval = String::with_capacity(rows * (width + 1));
+
+    for _ in 0..rows {
+        for _ in 0..width {
+            retval.push(if rng.next_bool() { '1' } else { '0' });
+        }
+        retval.push('\n');
+    }
+
+    retval
+}
+
+// a million 100-bit rows; slow in debug builds, so run it on request with
+// `cargo test --release --bin day3 -- --ignored --nocapture`
+#[test]
+#[ignore]
+fn million_rows() {
+    let big = Diagnostics::from_str(&synthetic(1_000_000, 100, 3)).unwrap();
+    assert_eq!((1_000_000, 100, 2), (big.rows, big.width, big.stride));
+
+    let start = Instant::now();
+    let (gamma, epsilon) = big
+        .rates(TiePolicy::PreferOne, TiePolicy::PreferZero)
+        .unwrap();
+    let o2 = big
+        .rating(Criterion::MostCommon, TiePolicy::PreferOne)
+        .unwrap();
+    println!(
+        "Synthetic {}x{} diagnostics: gamma {}, O2 rating {} ({:?})",
+        big.rows,
+        big.width,
+        gamma,
+        o2,
+        start.elapsed()
+    );
+
+    assert_eq!(Err(Oopsie::TooWide(100)), usize::try_from(&gamma));
+    assert!(u128::try_from(&epsilon).is_ok());
+    assert!((0..big.rows).any(|row| big.datum(row) == o2));
+    let co2 = big
+        .rating(Criterion::LeastCommon, TiePolicy::PreferZero)
+        .unwrap();
+    assert!((0..big.rows).any(|row| big.datum(row) == co2));
+}
+
 fn day3() -> Result<(), Oopsie> {
-    let diagnostics = Diagnostics::from_str(DAY3A_DATA).unwrap();
+    let diagnostics = Diagnostics::from_str(DAY3A_DATA)?;
 
-    let (gamma_rate, epsilon_rate) = diagnostics.get_rates();
+    let (gamma_rate, epsilon_rate) = diagnostics.get_rates()?;
     println!(
         "For part #1, computed gamma rate {}, epsilon rate {}, for a power consumption {}",
         gamma_rate,
@@ -217,6 +611,16 @@ fn day3() -> Result<(), Oopsie> {
         co2_scrubber_rating,
         o2_generator_rating * co2_scrubber_rating
     );
+
+    let (trace, co2) = diagnostics.rating_trace(Criterion::LeastCommon, TiePolicy::Error);
+    for step in trace.iter().filter(|x| x.survivors.len() <= 4) {
+        println!("CO2 {}", step);
+    }
+    match co2 {
+        Ok(co2) => println!("Strict CO2 scrubber rating: {}", co2),
+        Err(e) => println!("Strict CO2 scrubber rating failed: {}", e),
+    }
+
     Ok(())
 }
 
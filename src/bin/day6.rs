@@ -18,7 +18,7 @@ struct LanternFishies {
     data: [usize; 9],
 }
 
-impl<'a> From<&'a [u8]> for LanternFishies {
+impl From<&[u8]> for LanternFishies {
     fn from(state: &[u8]) -> LanternFishies {
         let mut data = [0; 9];
 
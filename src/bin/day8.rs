@@ -1,33 +1,49 @@
-use itertools::Itertools;
+use advent2021::prng::Prng;
+use core::fmt;
 use std::collections::{HashMap, HashSet};
+use thiserror::Error;
 
 #[cfg(test)]
 const TEST_DATA: &str = include_str!("../../data/day8t.txt");
 const DAY8_DATA: &str = include_str!("../../data/day8a.txt");
 
+#[derive(Debug, Error, PartialEq)]
+enum Oopsie {
+    #[error("Wire {0} could be any of {1:?}")]
+    Ambiguous(char, Vec<Segment>),
+    #[error("Wire {0} doesn't behave like any segment")]
+    NoSegment(char),
+    #[error("Wires {0} and {1} both look like {2:?}")]
+    SharedSegment(char, char, Segment),
+    #[error("Pattern {0} isn't a digit under the deduced wiring")]
+    NotADigit(String),
+    #[error("Pattern {0} shows up more than once")]
+    RepeatedDigit(String),
+    #[error("Glyph {0} doesn't light any segments")]
+    EmptyGlyph(usize),
+    #[error("Glyphs {0} and {1} light the same segments")]
+    DuplicateGlyph(usize, usize),
+    #[error("Segments {0:?} and {1:?} are lit in glyphs of the same lengths, so no wiring can tell them apart")]
+    IndistinguishableSegments(Segment, Segment),
+    #[error("Font has no glyph {0}")]
+    NoSuchGlyph(usize),
+    #[error("Font has {0} glyphs, but got {1} patterns")]
+    WrongPatternCount(usize, usize),
+}
+
 #[derive(Clone)]
 struct ProblemInput<'a> {
-    signal_patterns: [&'a str; 10],
-    output_values: [&'a str; 4],
+    signal_patterns: Vec<&'a str>,
+    output_values: Vec<&'a str>,
 }
 
 impl<'a> From<&'a str> for ProblemInput<'a> {
     fn from(x: &'a str) -> Self {
         let (signals, outputs) = x.split_once(" | ").unwrap();
-        let mut signal_patterns = [""; 10];
-        let mut output_values = [""; 4];
-
-        for (i, val) in signals.split(' ').enumerate() {
-            signal_patterns[i] = val;
-        }
-
-        for (i, val) in outputs.split(' ').enumerate() {
-            output_values[i] = val;
-        }
 
         ProblemInput {
-            signal_patterns,
-            output_values,
+            signal_patterns: signals.split(' ').collect(),
+            output_values: outputs.split(' ').collect(),
         }
     }
 }
@@ -38,10 +54,15 @@ fn problem_input() {
         "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf";
     let parsed = ProblemInput::from(input);
     assert_eq!(
-        ["acedgfb", "cdfbe", "gcdfa", "fbcad", "dab", "cefabd", "cdfgeb", "eafb", "cagedb", "ab"],
+        vec![
+            "acedgfb", "cdfbe", "gcdfa", "fbcad", "dab", "cefabd", "cdfgeb", "eafb", "cagedb", "ab"
+        ],
         parsed.signal_patterns
     );
-    assert_eq!(["cdfeb", "fcadb", "cdfeb", "cdbaf"], parsed.output_values);
+    assert_eq!(
+        vec!["cdfeb", "fcadb", "cdfeb", "cdbaf"],
+        parsed.output_values
+    );
 }
 
 fn from_file_data(input_file: &str) -> Vec<ProblemInput<'_>> {
@@ -54,7 +75,7 @@ fn from_file_data(input_file: &str) -> Vec<ProblemInput<'_>> {
     retval
 }
 
-#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 enum Segment {
     Top,
     TopLeft,
@@ -65,6 +86,16 @@ enum Segment {
     Bottom,
 }
 
+const ALL_SEGMENTS: [Segment; 7] = [
+    Segment::Top,
+    Segment::TopLeft,
+    Segment::TopRight,
+    Segment::Middle,
+    Segment::BottomLeft,
+    Segment::BottomRight,
+    Segment::Bottom,
+];
+
 const SEGMENTS_USED: [&[Segment]; 10] = [
     /* 0 */
     &[
@@ -138,44 +169,213 @@ const SEGMENTS_USED: [&[Segment]; 10] = [
     ],
 ];
 
-fn recognize_number(segs: &[Segment]) -> Option<usize> {
-    for (result, digit_info) in SEGMENTS_USED.iter().enumerate() {
-        if digit_info.len() == segs.len() && segs.iter().all(|x| digit_info.contains(x)) {
-            return Some(result);
+const HEX_SEGMENTS_USED: [&[Segment]; 6] = [
+    /* A */
+    &[
+        Segment::Top,
+        Segment::TopLeft,
+        Segment::TopRight,
+        Segment::Middle,
+        Segment::BottomLeft,
+        Segment::BottomRight,
+    ],
+    /* b */
+    &[
+        Segment::TopLeft,
+        Segment::Middle,
+        Segment::BottomLeft,
+        Segment::BottomRight,
+        Segment::Bottom,
+    ],
+    /* C */
+    &[
+        Segment::Top,
+        Segment::TopLeft,
+        Segment::BottomLeft,
+        Segment::Bottom,
+    ],
+    /* d */
+    &[
+        Segment::TopRight,
+        Segment::Middle,
+        Segment::BottomLeft,
+        Segment::BottomRight,
+        Segment::Bottom,
+    ],
+    /* E */
+    &[
+        Segment::Top,
+        Segment::TopLeft,
+        Segment::Middle,
+        Segment::BottomLeft,
+        Segment::Bottom,
+    ],
+    /* F */
+    &[
+        Segment::Top,
+        Segment::TopLeft,
+        Segment::Middle,
+        Segment::BottomLeft,
+    ],
+];
+
+#[derive(Clone, Debug)]
+struct Font {
+    glyphs: Vec<Vec<Segment>>,
+}
+
+impl Font {
+    fn new(mut glyphs: Vec<Vec<Segment>>) -> Result<Font, Oopsie> {
+        for (i, glyph) in glyphs.iter_mut().enumerate() {
+            glyph.sort_unstable();
+            glyph.dedup();
+            if glyph.is_empty() {
+                return Err(Oopsie::EmptyGlyph(i));
+            }
         }
+
+        for (i, glyph) in glyphs.iter().enumerate() {
+            if let Some(j) = glyphs[..i].iter().position(|x| x == glyph) {
+                return Err(Oopsie::DuplicateGlyph(j, i));
+            }
+        }
+
+        let font = Font { glyphs };
+        let signatures: Vec<Vec<usize>> = ALL_SEGMENTS
+            .iter()
+            .map(|x| font.segment_signature(*x))
+            .collect();
+        for (i, signature) in signatures.iter().enumerate() {
+            if let Some(j) = signatures[..i].iter().position(|x| x == signature) {
+                return Err(Oopsie::IndistinguishableSegments(
+                    ALL_SEGMENTS[j],
+                    ALL_SEGMENTS[i],
+                ));
+            }
+        }
+
+        Ok(font)
+    }
+
+    fn standard() -> Font {
+        Font::new(SEGMENTS_USED.iter().map(|x| x.to_vec()).collect()).unwrap()
+    }
+
+    fn hex() -> Font {
+        let glyphs = SEGMENTS_USED.iter().chain(HEX_SEGMENTS_USED.iter());
+        Font::new(glyphs.map(|x| x.to_vec()).collect()).unwrap()
     }
 
-    None
+    fn with_glyph(mut self, index: usize, segments: &[Segment]) -> Result<Font, Oopsie> {
+        match self.glyphs.get_mut(index) {
+            None => return Err(Oopsie::NoSuchGlyph(index)),
+            Some(glyph) => *glyph = segments.to_vec(),
+        }
+        Font::new(self.glyphs)
+    }
+
+    fn len(&self) -> usize {
+        self.glyphs.len()
+    }
+
+    fn recognize(&self, segs: &[Segment]) -> Option<usize> {
+        self.glyphs
+            .iter()
+            .position(|x| x.len() == segs.len() && segs.iter().all(|s| x.contains(s)))
+    }
+
+    // lengths that only one glyph has, so a pattern that long gives itself away
+    fn unique_lengths(&self) -> Vec<usize> {
+        let mut result: Vec<usize> = self
+            .glyphs
+            .iter()
+            .map(|x| x.len())
+            .filter(|len| self.glyphs.iter().filter(|x| x.len() == *len).count() == 1)
+            .collect();
+        result.sort_unstable();
+        result
+    }
+
+    // the sorted lengths of every glyph a segment takes part in
+    fn segment_signature(&self, segment: Segment) -> Vec<usize> {
+        let mut result: Vec<usize> = self
+            .glyphs
+            .iter()
+            .filter(|x| x.contains(&segment))
+            .map(|x| x.len())
+            .collect();
+        result.sort_unstable();
+        result
+    }
 }
 
-fn reasonable_assignments(assigns: &HashMap<char, Segment>) -> bool {
-    let mut tester = HashSet::new();
+#[derive(Clone, Debug, PartialEq)]
+enum Deduction {
+    Frequency(usize),
+    Intersection {
+        frequency: usize,
+        length: usize,
+        member: bool,
+    },
+    Signature(Vec<usize>),
+}
 
-    for value in assigns.values() {
-        if tester.contains(value) {
-            return false;
+impl fmt::Display for Deduction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Deduction::Frequency(count) => {
+                write!(f, "it's the only wire lit in {} patterns", count)
+            }
+            Deduction::Intersection {
+                frequency,
+                length,
+                member,
+            } => write!(
+                f,
+                "it's lit in {} patterns and {} the only {}-wire pattern",
+                frequency,
+                if *member {
+                    "is part of"
+                } else {
+                    "isn't part of"
+                },
+                length
+            ),
+            Deduction::Signature(lengths) => {
+                write!(f, "only it is lit in patterns of lengths {:?}", lengths)
+            }
         }
-        tester.insert(value);
     }
+}
 
-    true
+struct Wiring {
+    segments: HashMap<char, Segment>,
+    reasons: Vec<(char, Segment, Deduction)>,
 }
 
-fn build_local_iterator<'a>(
-    input: (&'a char, &'a HashSet<Segment>),
-) -> impl Iterator<Item = (char, Segment)> + Clone + 'a {
-    let (c, set) = input;
-    set.iter().map(|x| (*c, *x))
+impl Wiring {
+    fn decode(&self, font: &Font, pattern: &str) -> Result<usize, Oopsie> {
+        let segments: Vec<Segment> = pattern
+            .chars()
+            .filter_map(|x| self.segments.get(&x))
+            .copied()
+            .collect();
+
+        if segments.len() != pattern.len() {
+            return Err(Oopsie::NotADigit(pattern.to_string()));
+        }
+        font.recognize(&segments)
+            .ok_or_else(|| Oopsie::NotADigit(pattern.to_string()))
+    }
 }
 
 impl<'a> ProblemInput<'a> {
-    fn unique_outputs(&self) -> usize {
+    fn unique_outputs(&self, font: &Font) -> usize {
+        let unique_lengths = font.unique_lengths();
         let mut result = 0;
 
         for v in self.output_values.iter() {
-            let len = v.len();
-
-            if len == 2 || len == 4 || len == 3 || len == 7 {
+            if unique_lengths.contains(&v.len()) {
                 result += 1;
             }
         }
@@ -183,112 +383,135 @@ impl<'a> ProblemInput<'a> {
         result
     }
 
-    fn find_letters_for(&self, len: usize) -> &str {
-        for x in self.signal_patterns {
-            if x.len() == len {
-                return x;
+    // why a wire can only be one segment, worked out from this line's own
+    // patterns: how often the wire is lit, then whether a pattern with a
+    // length nothing else shares separates it from wires lit just as often
+    fn explain(&self, wire: char) -> Deduction {
+        let frequency = |wire: char| {
+            self.signal_patterns
+                .iter()
+                .filter(|x| x.contains(wire))
+                .count()
+        };
+        let count = frequency(wire);
+        let rivals: Vec<char> = ('a'..='g')
+            .filter(|x| *x != wire && frequency(*x) == count)
+            .collect();
+
+        if rivals.is_empty() {
+            return Deduction::Frequency(count);
+        }
+
+        let mut giveaways: Vec<&str> = self
+            .signal_patterns
+            .iter()
+            .filter(|x| {
+                self.signal_patterns
+                    .iter()
+                    .filter(|y| y.len() == x.len())
+                    .count()
+                    == 1
+            })
+            .copied()
+            .collect();
+        giveaways.sort_unstable_by_key(|x| x.len());
+
+        for pattern in giveaways {
+            let length = pattern.len();
+            let member = pattern.contains(wire);
+            if rivals.iter().all(|x| pattern.contains(*x) != member) {
+                return Deduction::Intersection {
+                    frequency: count,
+                    length,
+                    member,
+                };
             }
         }
-        panic!("Couldn't find letters for size {}", len);
+
+        Deduction::Signature(self.wire_signature(wire))
     }
 
-    fn possible_assignments(&self) -> HashMap<char, HashSet<Segment>> {
-        let mut result = HashMap::new();
+    fn wire_signature(&self, wire: char) -> Vec<usize> {
+        let mut result: Vec<usize> = self
+            .signal_patterns
+            .iter()
+            .filter(|x| x.contains(wire))
+            .map(|x| x.len())
+            .collect();
+        result.sort_unstable();
+        result
+    }
 
-        for label in 'a'..='g' {
-            result.insert(label, SEGMENTS_USED[8].iter().copied().collect());
+    fn deduce(&self, font: &Font) -> Result<Wiring, Oopsie> {
+        if self.signal_patterns.len() != font.len() {
+            return Err(Oopsie::WrongPatternCount(
+                font.len(),
+                self.signal_patterns.len(),
+            ));
         }
 
-        let one = self.find_letters_for(2);
-        let four = self.find_letters_for(4);
-        let seven = self.find_letters_for(3);
+        let mut segments = HashMap::new();
+        let mut reasons = Vec::new();
+        let mut claimed: HashMap<Segment, char> = HashMap::new();
 
-        for letter in one.chars() {
-            result.insert(
-                letter,
-                HashSet::from([Segment::TopRight, Segment::BottomRight]),
-            );
-        }
+        for wire in 'a'..='g' {
+            let signature = self.wire_signature(wire);
+            let candidates: Vec<Segment> = ALL_SEGMENTS
+                .iter()
+                .filter(|x| font.segment_signature(**x) == signature)
+                .copied()
+                .collect();
 
-        for letter in four.chars() {
-            if !one.contains(letter) {
-                result.insert(letter, HashSet::from([Segment::TopLeft, Segment::Middle]));
+            let segment = match candidates[..] {
+                [] => return Err(Oopsie::NoSegment(wire)),
+                [segment] => segment,
+                _ => return Err(Oopsie::Ambiguous(wire, candidates)),
+            };
+            if let Some(other) = claimed.insert(segment, wire) {
+                return Err(Oopsie::SharedSegment(other, wire, segment));
             }
-        }
 
-        for letter in seven.chars() {
-            if !one.contains(letter) {
-                result.insert(letter, HashSet::from([Segment::Top]));
-            }
+            segments.insert(wire, segment);
+            reasons.push((wire, segment, self.explain(wire)));
         }
 
-        result
-    }
-
-    fn assignments_validate(&self, assignments: &HashMap<char, Segment>) -> bool {
+        let wiring = Wiring { segments, reasons };
         let mut found = HashSet::new();
-
-        for input in self.signal_patterns {
-            let segments: Vec<Segment> = input
-                .chars()
-                .map(|x| assignments.get(&x).unwrap())
-                .copied()
-                .collect();
-            match recognize_number(&segments) {
-                None => return false,
-                Some(x) if found.contains(&x) => return false,
-                Some(x) => {
-                    found.insert(x);
-                }
+        for pattern in self.signal_patterns.iter() {
+            if !found.insert(wiring.decode(font, pattern)?) {
+                return Err(Oopsie::RepeatedDigit(pattern.to_string()));
             }
         }
 
-        true
+        Ok(wiring)
     }
 
-    fn compute_output(&self, assignments: HashMap<char, Segment>) -> Option<usize> {
-        let mut result = 0;
+    fn decode_outputs(&self, font: &Font) -> Result<Vec<usize>, Oopsie> {
+        let wiring = self.deduce(font)?;
 
-        for output in self.output_values.iter() {
-            let segments: Vec<Segment> = output
-                .chars()
-                .map(|x| assignments.get(&x).unwrap())
-                .copied()
-                .collect();
-            let value = recognize_number(&segments)?;
-            result = (result * 10) + value;
-        }
-
-        Some(result)
+        self.output_values
+            .iter()
+            .map(|x| wiring.decode(font, x))
+            .collect()
     }
 
-    fn solve(&self) -> Option<usize> {
-        let possible_sets = self.possible_assignments();
-        let possibles = possible_sets
-            .iter()
-            .map(build_local_iterator)
-            .multi_cartesian_product()
-            .map(|x| HashMap::from_iter(x.iter().cloned()))
-            .filter(reasonable_assignments);
-
-        for assignments in possibles {
-            if self.assignments_validate(&assignments) {
-                if let Some(result) = self.compute_output(assignments) {
-                    return Some(result);
-                }
-            }
+    fn solve(&self, font: &Font) -> Result<usize, Oopsie> {
+        let mut result = 0;
+
+        for digit in self.decode_outputs(font)? {
+            result = (result * font.len()) + digit;
         }
 
-        None
+        Ok(result)
     }
 }
 
-fn count_unique_outputs(inputs: &[ProblemInput]) -> usize {
-    inputs.iter().map(|x| x.unique_outputs()).sum()
+fn count_unique_outputs(inputs: &[ProblemInput], font: &Font) -> usize {
+    inputs.iter().map(|x| x.unique_outputs(font)).sum()
 }
 
-fn sum_outputs(inputs: &[ProblemInput]) -> usize {
-    inputs.iter().map(|x| x.solve().unwrap()).sum()
+fn sum_outputs(inputs: &[ProblemInput], font: &Font) -> Result<usize, Oopsie> {
+    inputs.iter().map(|x| x.solve(font)).sum()
 }
 
 #[test]
@@ -296,17 +519,306 @@ fn regression() {
     let example_input =
         "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf";
     let example = ProblemInput::from(example_input);
-    assert_eq!(5353, sum_outputs(&[example]));
+    let font = Font::standard();
+    assert_eq!(Ok(5353), sum_outputs(&[example], &font));
 
     let test_data = from_file_data(TEST_DATA);
-    assert_eq!(26, count_unique_outputs(&test_data));
-    assert_eq!(61229, sum_outputs(&test_data));
+    assert_eq!(26, count_unique_outputs(&test_data, &font));
+    assert_eq!(Ok(61229), sum_outputs(&test_data, &font));
     let real_data = from_file_data(DAY8_DATA);
-    assert_eq!(519, count_unique_outputs(&real_data));
+    assert_eq!(519, count_unique_outputs(&real_data, &font));
 }
 
-fn main() {
+#[test]
+fn deductions() {
+    let example = ProblemInput::from(
+        "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+    );
+    let font = Font::standard();
+    let wiring = example.deduce(&font).unwrap();
+    assert_eq!(Some(&Segment::Top), wiring.segments.get(&'d'));
+    assert_eq!(Some(&Segment::BottomLeft), wiring.segments.get(&'g'));
+    assert_eq!(
+        (
+            'd',
+            Segment::Top,
+            Deduction::Intersection {
+                frequency: 8,
+                length: 2,
+                member: false
+            }
+        ),
+        wiring.reasons[3]
+    );
+    assert_eq!(
+        ('g', Segment::BottomLeft, Deduction::Frequency(4)),
+        wiring.reasons[6]
+    );
+    assert_eq!(
+        (
+            'f',
+            Segment::Middle,
+            Deduction::Intersection {
+                frequency: 7,
+                length: 4,
+                member: true
+            }
+        ),
+        wiring.reasons[5]
+    );
+    assert_eq!(Deduction::Frequency(6), example.explain('e'));
+
+    // the explanation comes from the patterns, so it still says something
+    // useful about lines the font can't make sense of
+    let unlit = ProblemInput::from("ab abc abcd abcdefg | ab");
+    assert_eq!(Deduction::Frequency(2), unlit.explain('d'));
+    assert_eq!(Deduction::Signature(vec![2, 3, 4, 7]), unlit.explain('a'));
+    assert_eq!(
+        Deduction::Intersection {
+            frequency: 1,
+            length: 2,
+            member: true
+        },
+        ProblemInput::from("ab acd | ab").explain('b')
+    );
+
+    let missing_wire = ProblemInput::from(
+        "acedgfb cdfbe gcdfa fbcad dab cefabd cdfeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+    );
+    assert!(missing_wire.solve(&font).is_err());
+    let duplicated = ProblemInput::from(
+        "acedgfb cdfbe cdfbe fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf",
+    );
+    assert!(duplicated.solve(&font).is_err());
+    let bad_output = ProblemInput::from(
+        "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cd",
+    );
+    assert_eq!(
+        Err(Oopsie::NotADigit("cd".to_string())),
+        bad_output.solve(&font)
+    );
+}
+
+fn random_wires(rng: &mut Prng) -> [char; 7] {
+    let mut wires = ['a', 'b', 'c', 'd', 'e', 'f', 'g'];
+    rng.shuffle(&mut wires);
+    wires
+}
+
+// wires[i] is the wire hooked up to ALL_SEGMENTS[i]; patterns and the
+// letters within them come out in a random order, like the puzzle input
+fn generate(
+    font: &Font,
+    wires: &[char; 7],
+    digits: &[usize],
+    rng: &mut Prng,
+) -> Result<String, Oopsie> {
+    let mut pattern = |glyph: &Vec<Segment>| -> String {
+        let mut letters: Vec<char> = glyph
+            .iter()
+            .map(|x| wires[ALL_SEGMENTS.iter().position(|s| s == x).unwrap()])
+            .collect();
+        rng.shuffle(&mut letters);
+        letters.into_iter().collect()
+    };
+    let mut signals: Vec<String> = font.glyphs.iter().map(&mut pattern).collect();
+    let mut outputs = Vec::with_capacity(digits.len());
+    for digit in digits.iter() {
+        let glyph = font.glyphs.get(*digit).ok_or(Oopsie::NoSuchGlyph(*digit))?;
+        outputs.push(pattern(glyph));
+    }
+    rng.shuffle(&mut signals);
+
+    Ok(format!("{} | {}", signals.join(" "), outputs.join(" ")))
+}
+
+fn render(font: &Font, digits: &[usize]) -> Result<String, Oopsie> {
+    let mut rows = [String::new(), String::new(), String::new()];
+
+    for (i, digit) in digits.iter().enumerate() {
+        let glyph = font.glyphs.get(*digit).ok_or(Oopsie::NoSuchGlyph(*digit))?;
+        let lit = |segment: Segment, c: char| if glyph.contains(&segment) { c } else { ' ' };
+
+        if i > 0 {
+            rows.iter_mut().for_each(|x| x.push(' '));
+        }
+        rows[0].extend([' ', lit(Segment::Top, '_'), ' ']);
+        rows[1].extend([
+            lit(Segment::TopLeft, '|'),
+            lit(Segment::Middle, '_'),
+            lit(Segment::TopRight, '|'),
+        ]);
+        rows[2].extend([
+            lit(Segment::BottomLeft, '|'),
+            lit(Segment::Bottom, '_'),
+            lit(Segment::BottomRight, '|'),
+        ]);
+    }
+
+    let mut result = String::new();
+    for row in rows.iter() {
+        result.push_str(row.trim_end());
+        result.push('\n');
+    }
+    Ok(result)
+}
+
+#[test]
+fn generated_inputs() {
+    let mut rng = Prng::new(8);
+    let alternate = Font::standard()
+        .with_glyph(
+            9,
+            &[
+                Segment::Top,
+                Segment::TopLeft,
+                Segment::TopRight,
+                Segment::Middle,
+                Segment::BottomRight,
+            ],
+        )
+        .unwrap();
+
+    for font in [Font::standard(), Font::hex(), alternate] {
+        for _ in 0..200 {
+            let wires = random_wires(&mut rng);
+            let digits: Vec<usize> = (0..4)
+                .map(|_| rng.below(font.len() as u64) as usize)
+                .collect();
+            let line = generate(&font, &wires, &digits, &mut rng).unwrap();
+            let input = ProblemInput::from(line.as_str());
+            assert_eq!(Ok(digits.clone()), input.decode_outputs(&font));
+            let wiring = input.deduce(&font).unwrap();
+            for (i, wire) in wires.iter().enumerate() {
+                assert_eq!(Some(&ALL_SEGMENTS[i]), wiring.segments.get(wire));
+            }
+        }
+    }
+
+    assert_eq!(
+        Err(Oopsie::NoSuchGlyph(10)),
+        generate(&Font::standard(), &random_wires(&mut rng), &[10], &mut rng)
+    );
+    assert_eq!(
+        " _       _   _       _   _   _   _   _\n| |   |  _|  _| |_| |_  |_    | |_| |_|\n|_|   | |_   _|   |  _| |_|   | |_|  _|\n",
+        render(&Font::standard(), &[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]).unwrap()
+    );
+    assert_eq!(
+        " _       _\n|_| |_  |    _|\n| | |_| |_  |_|\n",
+        render(&Font::hex(), &[10, 11, 12, 13]).unwrap()
+    );
+}
+
+#[test]
+fn fonts() {
+    let wires = ['d', 'e', 'a', 'f', 'g', 'b', 'c'];
+    let hex = Font::hex();
+    let mut rng = Prng::new(41);
+    let line = generate(&hex, &wires, &[10, 1, 12, 13], &mut rng).unwrap();
+    let input = ProblemInput::from(line.as_str());
+    assert_eq!(Ok(0xa1cd), input.solve(&hex));
+    assert_eq!(vec![2, 3, 7], hex.unique_lengths());
+    assert_eq!(1, input.unique_outputs(&hex));
+    assert_eq!(
+        Err(Oopsie::WrongPatternCount(10, 16)),
+        input.solve(&Font::standard()).map(|_| ())
+    );
+
+    let alternate = Font::standard()
+        .with_glyph(
+            7,
+            &[
+                Segment::Top,
+                Segment::TopLeft,
+                Segment::TopRight,
+                Segment::BottomRight,
+            ],
+        )
+        .unwrap()
+        .with_glyph(
+            9,
+            &[
+                Segment::Top,
+                Segment::TopLeft,
+                Segment::TopRight,
+                Segment::Middle,
+                Segment::BottomRight,
+            ],
+        )
+        .unwrap();
+    assert_eq!(vec![2, 7], alternate.unique_lengths());
+    let line = generate(&alternate, &wires, &[7, 9, 4, 1], &mut rng).unwrap();
+    let input = ProblemInput::from(line.as_str());
+    assert_eq!(Ok(7941), input.solve(&alternate));
+    assert_eq!(1, input.unique_outputs(&alternate));
+    assert_eq!(3, input.unique_outputs(&Font::standard()));
+
+    assert!(matches!(
+        Font::standard().with_glyph(7, &[Segment::TopRight, Segment::BottomRight]),
+        Err(Oopsie::DuplicateGlyph(1, 7))
+    ));
+    assert!(matches!(
+        Font::standard().with_glyph(3, &[]),
+        Err(Oopsie::EmptyGlyph(3))
+    ));
+    assert!(matches!(
+        Font::standard().with_glyph(10, &[Segment::Top]),
+        Err(Oopsie::NoSuchGlyph(10))
+    ));
+    assert!(matches!(
+        Font::new(vec![
+            vec![Segment::TopRight, Segment::BottomRight],
+            vec![Segment::TopLeft, Segment::BottomLeft],
+        ]),
+        Err(Oopsie::IndistinguishableSegments(
+            Segment::TopLeft,
+            Segment::TopRight
+        ))
+    ));
+}
+
+fn main() -> Result<(), Oopsie> {
+    let font = Font::standard();
     let real_data = from_file_data(DAY8_DATA);
-    println!("Part #1: {}", count_unique_outputs(&real_data));
-    println!("Part #2: {}", sum_outputs(&real_data));
+    println!("Part #1: {}", count_unique_outputs(&real_data, &font));
+    println!("Part #2: {}", sum_outputs(&real_data, &font)?);
+    for (wire, segment, reason) in real_data[0].deduce(&font)?.reasons {
+        println!("  {} is {:?} because {}", wire, segment, reason);
+    }
+    print!("{}", render(&font, &real_data[0].decode_outputs(&font)?)?);
+
+    let mut rng = Prng::new(2021);
+    let mut agreed = 0;
+    for _ in 0..1000 {
+        let wires = random_wires(&mut rng);
+        let digits: Vec<usize> = (0..4).map(|_| rng.below(10) as usize).collect();
+        let line = generate(&font, &wires, &digits, &mut rng)?;
+        if ProblemInput::from(line.as_str()).decode_outputs(&font)? == digits {
+            agreed += 1;
+        }
+    }
+    println!("Solver agreed with {} of 1000 generated lines", agreed);
+
+    let alternate = font.clone().with_glyph(
+        7,
+        &[
+            Segment::Top,
+            Segment::TopLeft,
+            Segment::TopRight,
+            Segment::BottomRight,
+        ],
+    )?;
+    for (name, font) in [
+        ("standard", font),
+        ("hex", Font::hex()),
+        ("alternate", alternate),
+    ] {
+        println!(
+            "{} font: {} glyphs, giveaway lengths {:?}",
+            name,
+            font.len(),
+            font.unique_lengths()
+        );
+    }
+    Ok(())
 }
@@ -30,7 +30,7 @@ impl TryFrom<char> for Value {
     }
 }
 
-fn basin_around(graph: &Graph<Value>, x: usize, y: usize) -> Vec<Point<Value>> {
+fn basin_around(graph: &Graph<Value>, x: usize, y: usize) -> Vec<Point<'_, Value>> {
     let base = match graph.get(x, y) {
         None => return Vec::new(),
         Some(p) => p,
@@ -68,7 +68,7 @@ impl<'a> Iterator for LowPoints<'a> {
             let candidate = self.underlying.next()?;
             let neighbors = self.graph.neighbors(candidate.x, candidate.y);
 
-            if neighbors.iter().all(|x| (*x).value.0 > candidate.value.0) {
+            if neighbors.iter().all(|x| x.value.0 > candidate.value.0) {
                 return Some(candidate);
             }
         }
@@ -0,0 +1,670 @@
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{self, BufRead, Write};
+use thiserror::Error;
+
+#[cfg(test)]
+const DAY10_TEST: &str = include_str!("../data/day10t.txt");
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Oopsie {
+    #[error("Delimiters can't be empty")]
+    EmptyDelimiter,
+    #[error("Escape character '{0}' is also part of a delimiter")]
+    EscapeClash(char),
+}
+
+// byte is an offset into the line, column counts characters from 1
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Position {
+    pub byte: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseResult {
+    IncompleteLine(Vec<String>),
+    IllegalCharacter(Position, char),
+    UnexpectedClose {
+        at: Position,
+        found: String,
+        expected: Option<String>,
+    },
+    Success,
+}
+
+impl ParseResult {
+    pub fn position(&self) -> Option<Position> {
+        match self {
+            ParseResult::IllegalCharacter(at, _) => Some(*at),
+            ParseResult::UnexpectedClose { at, .. } => Some(*at),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum Repair {
+    Append(String),
+    Insert {
+        at: Position,
+        text: String,
+    },
+    Replace {
+        at: Position,
+        len: usize,
+        text: String,
+    },
+    Delete {
+        at: Position,
+        len: usize,
+    },
+}
+
+impl Repair {
+    pub fn apply(&self, line: &str) -> String {
+        match self {
+            Repair::Append(text) => format!("{}{}", line, text),
+            Repair::Insert { at, text } => {
+                format!("{}{}{}", &line[..at.byte], text, &line[at.byte..])
+            }
+            Repair::Replace { at, len, text } => {
+                format!("{}{}{}", &line[..at.byte], text, &line[at.byte + len..])
+            }
+            Repair::Delete { at, len } => {
+                format!("{}{}", &line[..at.byte], &line[at.byte + len..])
+            }
+        }
+    }
+}
+
+struct Delimiter {
+    open: String,
+    close: String,
+    // nothing inside a quote counts until its closer shows up
+    quote: bool,
+}
+
+pub struct DelimiterSet {
+    delimiters: Vec<Delimiter>,
+    escape: Option<char>,
+}
+
+impl Default for DelimiterSet {
+    fn default() -> Self {
+        [("(", ")"), ("[", "]"), ("{", "}"), ("<", ">")]
+            .iter()
+            .try_fold(DelimiterSet::empty(), |set, (open, close)| {
+                set.with_pair(open, close)
+            })
+            .unwrap()
+    }
+}
+
+impl DelimiterSet {
+    pub fn empty() -> Self {
+        DelimiterSet {
+            delimiters: Vec::new(),
+            escape: None,
+        }
+    }
+
+    fn add(mut self, open: &str, close: &str, quote: bool) -> Result<Self, Oopsie> {
+        if open.is_empty() || close.is_empty() {
+            return Err(Oopsie::EmptyDelimiter);
+        }
+        if let Some(escape) = self.escape {
+            if open.contains(escape) || close.contains(escape) {
+                return Err(Oopsie::EscapeClash(escape));
+            }
+        }
+
+        self.delimiters.push(Delimiter {
+            open: open.to_string(),
+            close: close.to_string(),
+            quote,
+        });
+        Ok(self)
+    }
+
+    pub fn with_pair(self, open: &str, close: &str) -> Result<Self, Oopsie> {
+        self.add(open, close, false)
+    }
+
+    pub fn with_quote(self, open: &str, close: &str) -> Result<Self, Oopsie> {
+        self.add(open, close, true)
+    }
+
+    pub fn with_escape(mut self, escape: char) -> Result<Self, Oopsie> {
+        if self
+            .delimiters
+            .iter()
+            .any(|x| x.open.contains(escape) || x.close.contains(escape))
+        {
+            return Err(Oopsie::EscapeClash(escape));
+        }
+
+        self.escape = Some(escape);
+        Ok(self)
+    }
+
+    fn longest<F>(&self, rest: &str, side: F) -> Option<&Delimiter>
+    where
+        F: Fn(&Delimiter) -> &str,
+    {
+        self.delimiters
+            .iter()
+            .filter(|x| rest.starts_with(side(x)))
+            .max_by_key(|x| side(x).len())
+    }
+
+    pub fn parse_line(&self, s: &str) -> ParseResult {
+        let mut open_stack: Vec<&Delimiter> = Vec::new();
+        let mut pos = 0;
+        let mut column = 1;
+
+        while let Some(c) = s[pos..].chars().next() {
+            let rest = &s[pos..];
+            let at = Position { byte: pos, column };
+
+            if Some(c) == self.escape {
+                match rest[c.len_utf8()..].chars().next() {
+                    None => return ParseResult::IllegalCharacter(at, c),
+                    Some(next) => {
+                        pos += c.len_utf8() + next.len_utf8();
+                        column += 2;
+                    }
+                }
+                continue;
+            }
+
+            if let Some(top) = open_stack.last() {
+                if rest.starts_with(&top.close) {
+                    pos += top.close.len();
+                    column += top.close.chars().count();
+                    open_stack.pop();
+                    continue;
+                }
+
+                if top.quote {
+                    pos += c.len_utf8();
+                    column += 1;
+                    continue;
+                }
+            }
+
+            if let Some(delimiter) = self.longest(rest, |x| &x.open) {
+                pos += delimiter.open.len();
+                column += delimiter.open.chars().count();
+                open_stack.push(delimiter);
+                continue;
+            }
+
+            return match self.longest(rest, |x| &x.close) {
+                None => ParseResult::IllegalCharacter(at, c),
+                Some(delimiter) => ParseResult::UnexpectedClose {
+                    at,
+                    found: delimiter.close.clone(),
+                    expected: open_stack.last().map(|x| x.close.clone()),
+                },
+            };
+        }
+
+        if open_stack.is_empty() {
+            ParseResult::Success
+        } else {
+            ParseResult::IncompleteLine(open_stack.iter().map(|x| x.close.clone()).collect())
+        }
+    }
+
+    // how far a line gets before its first error, and then how little it
+    // still owes; bigger is better
+    fn progress(&self, line: &str) -> (usize, Reverse<usize>) {
+        match self.parse_line(line) {
+            ParseResult::Success => (usize::MAX, Reverse(0)),
+            ParseResult::IncompleteLine(owed) => (usize::MAX, Reverse(owed.len())),
+            ParseResult::IllegalCharacter(at, _) => (at.byte, Reverse(0)),
+            ParseResult::UnexpectedClose { at, .. } => (at.byte, Reverse(0)),
+        }
+    }
+
+    pub fn repair(&self, line: &str, result: &ParseResult) -> Option<Repair> {
+        match result {
+            ParseResult::Success => None,
+            ParseResult::IncompleteLine(owed) => {
+                Some(Repair::Append(owed.iter().rev().cloned().collect()))
+            }
+            ParseResult::IllegalCharacter(at, c) => Some(Repair::Delete {
+                at: *at,
+                len: c.len_utf8(),
+            }),
+            ParseResult::UnexpectedClose {
+                at,
+                found,
+                expected,
+            } => {
+                let mut candidates = Vec::new();
+
+                if let Some(expected) = expected {
+                    candidates.push(Repair::Replace {
+                        at: *at,
+                        len: found.len(),
+                        text: expected.clone(),
+                    });
+                    candidates.push(Repair::Insert {
+                        at: *at,
+                        text: expected.clone(),
+                    });
+                }
+                candidates.push(Repair::Delete {
+                    at: *at,
+                    len: found.len(),
+                });
+
+                candidates
+                    .into_iter()
+                    .min_by_key(|x| Reverse(self.progress(&x.apply(line))))
+            }
+        }
+    }
+}
+
+pub struct SyntaxScoring {
+    points: HashMap<String, u64>,
+    illegal: u64,
+}
+
+impl Default for SyntaxScoring {
+    fn default() -> Self {
+        SyntaxScoring::new(&[(")", 3), ("]", 57), ("}", 1197), (">", 25137)])
+    }
+}
+
+impl SyntaxScoring {
+    pub fn new(points: &[(&str, u64)]) -> Self {
+        SyntaxScoring {
+            points: points.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+            illegal: 0,
+        }
+    }
+
+    pub fn with_illegal(mut self, points: u64) -> Self {
+        self.illegal = points;
+        self
+    }
+
+    pub fn score(&self, result: &ParseResult) -> u64 {
+        match result {
+            ParseResult::UnexpectedClose { found, .. } => {
+                self.points.get(found).copied().unwrap_or_default()
+            }
+            ParseResult::IllegalCharacter(_, _) => self.illegal,
+            _ => 0,
+        }
+    }
+}
+
+pub struct CompletionScoring {
+    base: u64,
+    points: HashMap<String, u64>,
+}
+
+impl Default for CompletionScoring {
+    fn default() -> Self {
+        CompletionScoring::new(5, &[(")", 1), ("]", 2), ("}", 3), (">", 4)])
+    }
+}
+
+impl CompletionScoring {
+    pub fn new(base: u64, points: &[(&str, u64)]) -> Self {
+        CompletionScoring {
+            base,
+            points: points.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+        }
+    }
+
+    pub fn score(&self, owed: &[String]) -> u64 {
+        let mut score = 0;
+
+        for close in owed.iter().rev() {
+            score = score * self.base + self.points.get(close).copied().unwrap_or_default();
+        }
+
+        score
+    }
+}
+
+// keeps the lower half of the values in a max-heap and the upper half in a
+// min-heap, with the upper half never smaller, so the median is always on top
+#[derive(Default)]
+pub struct RunningMedian {
+    lower: BinaryHeap<u64>,
+    upper: BinaryHeap<Reverse<u64>>,
+}
+
+impl RunningMedian {
+    pub fn push(&mut self, value: u64) {
+        match self.upper.peek() {
+            Some(Reverse(x)) if value < *x => self.lower.push(value),
+            _ => self.upper.push(Reverse(value)),
+        }
+
+        if self.lower.len() > self.upper.len() {
+            let moved = self.lower.pop().unwrap();
+            self.upper.push(Reverse(moved));
+        } else if self.upper.len() > self.lower.len() + 1 {
+            let Reverse(moved) = self.upper.pop().unwrap();
+            self.lower.push(moved);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lower.len() + self.upper.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.upper.is_empty()
+    }
+
+    // the upper middle when there's an even number of values
+    pub fn median(&self) -> Option<u64> {
+        self.upper.peek().map(|Reverse(x)| *x)
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Statistics {
+    pub lines: usize,
+    pub clean: usize,
+    pub corrupted: usize,
+    pub incomplete: usize,
+    pub illegal: usize,
+    pub syntax_score: u64,
+}
+
+fn json_string(s: &str) -> String {
+    let mut result = String::with_capacity(s.len() + 2);
+
+    result.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+
+    result
+}
+
+pub struct StreamValidator<'a> {
+    delimiters: &'a DelimiterSet,
+    syntax: &'a SyntaxScoring,
+    completion: &'a CompletionScoring,
+    statistics: Statistics,
+    completions: RunningMedian,
+}
+
+impl<'a> StreamValidator<'a> {
+    pub fn new(
+        delimiters: &'a DelimiterSet,
+        syntax: &'a SyntaxScoring,
+        completion: &'a CompletionScoring,
+    ) -> Self {
+        StreamValidator {
+            delimiters,
+            syntax,
+            completion,
+            statistics: Statistics::default(),
+            completions: RunningMedian::default(),
+        }
+    }
+
+    pub fn statistics(&self) -> &Statistics {
+        &self.statistics
+    }
+
+    pub fn median_completion(&self) -> Option<u64> {
+        self.completions.median()
+    }
+
+    // folds one line into the running statistics, handing back its JSON record
+    pub fn push(&mut self, line: &str) -> String {
+        let result = self.delimiters.parse_line(line);
+        let repair = self.delimiters.repair(line, &result);
+
+        self.statistics.lines += 1;
+        let mut fields = vec![format!("\"line\":{}", self.statistics.lines)];
+        match &result {
+            ParseResult::Success => {
+                self.statistics.clean += 1;
+                fields.push("\"status\":\"ok\"".to_string());
+            }
+            ParseResult::IncompleteLine(owed) => {
+                let score = self.completion.score(owed);
+                self.statistics.incomplete += 1;
+                self.completions.push(score);
+                fields.push("\"status\":\"incomplete\"".to_string());
+                fields.push(format!("\"score\":{}", score));
+            }
+            ParseResult::IllegalCharacter(at, c) => {
+                let score = self.syntax.score(&result);
+                self.statistics.illegal += 1;
+                self.statistics.syntax_score += score;
+                fields.push("\"status\":\"illegal\"".to_string());
+                fields.push(format!("\"byte\":{},\"column\":{}", at.byte, at.column));
+                fields.push(format!("\"found\":{}", json_string(&c.to_string())));
+                fields.push(format!("\"score\":{}", score));
+            }
+            ParseResult::UnexpectedClose {
+                at,
+                found,
+                expected,
+            } => {
+                let score = self.syntax.score(&result);
+                self.statistics.corrupted += 1;
+                self.statistics.syntax_score += score;
+                fields.push("\"status\":\"corrupted\"".to_string());
+                fields.push(format!("\"byte\":{},\"column\":{}", at.byte, at.column));
+                fields.push(format!("\"found\":{}", json_string(found)));
+                fields.push(format!(
+                    "\"expected\":{}",
+                    expected.as_deref().map_or("null".to_string(), json_string)
+                ));
+                fields.push(format!("\"score\":{}", score));
+            }
+        }
+        if let Some(repair) = repair {
+            fields.push(format!("\"repaired\":{}", json_string(&repair.apply(line))));
+        }
+
+        format!("{{{}}}", fields.join(","))
+    }
+
+    pub fn run<R: BufRead, W: Write>(&mut self, input: R, mut output: W) -> io::Result<()> {
+        for line in input.lines() {
+            writeln!(output, "{}", self.push(&line?))?;
+        }
+
+        output.flush()
+    }
+}
+
+#[test]
+fn delimiter_sets() {
+    let standard = DelimiterSet::default();
+    let syntax = SyntaxScoring::default();
+    let completion = CompletionScoring::default();
+    let mut corrupted = 0;
+    let mut incomplete = Vec::new();
+    for line in DAY10_TEST.lines() {
+        let result = standard.parse_line(line);
+        corrupted += syntax.score(&result);
+        if let ParseResult::IncompleteLine(owed) = result {
+            incomplete.push(completion.score(&owed));
+        }
+    }
+    incomplete.sort_unstable();
+    assert_eq!(26397, corrupted);
+    assert_eq!(288957, incomplete[incomplete.len() / 2]);
+
+    let custom = DelimiterSet::empty()
+        .with_pair("(", ")")
+        .unwrap()
+        .with_pair("/*", "*/")
+        .unwrap()
+        .with_quote("\"", "\"")
+        .unwrap()
+        .with_escape('\\')
+        .unwrap();
+    assert_eq!(ParseResult::Success, custom.parse_line("(/*\"(]\\\"\"*/)"));
+    assert_eq!(
+        ParseResult::IncompleteLine(vec![")".to_string(), "*/".to_string()]),
+        custom.parse_line("(/*()")
+    );
+    assert!(matches!(
+        DelimiterSet::default().with_pair("", ")"),
+        Err(Oopsie::EmptyDelimiter)
+    ));
+
+    let custom_completion = CompletionScoring::new(10, &[(")", 1), ("*/", 2)]);
+    assert_eq!(
+        21,
+        custom_completion.score(&[")".to_string(), "*/".to_string()])
+    );
+    let custom_syntax = SyntaxScoring::new(&[("*/", 100)]).with_illegal(7);
+    assert_eq!(100, custom_syntax.score(&custom.parse_line("(()*/")));
+    assert_eq!(7, custom_syntax.score(&custom.parse_line("(x)")));
+    assert!(matches!(
+        custom.with_pair("\\(", ")"),
+        Err(Oopsie::EscapeClash('\\'))
+    ));
+}
+
+#[test]
+fn positions_and_repairs() {
+    let standard = DelimiterSet::default();
+    let line = "{([(<{}[<>[]}>{[]{[(<()>";
+    let result = standard.parse_line(line);
+    assert_eq!(
+        ParseResult::UnexpectedClose {
+            at: Position {
+                byte: 12,
+                column: 13
+            },
+            found: "}".to_string(),
+            expected: Some("]".to_string()),
+        },
+        result
+    );
+    let repair = standard.repair(line, &result).unwrap();
+    assert_eq!(
+        Repair::Replace {
+            at: Position {
+                byte: 12,
+                column: 13
+            },
+            len: 1,
+            text: "]".to_string()
+        },
+        repair
+    );
+    assert_eq!("{([(<{}[<>[]]>{[]{[(<()>", repair.apply(line));
+
+    let line = "[({(<(())[]>[[{[]{<()<>>";
+    let result = standard.parse_line(line);
+    let repair = standard.repair(line, &result).unwrap();
+    assert_eq!(Repair::Append("}}]])})]".to_string()), repair);
+    assert_eq!(
+        ParseResult::Success,
+        standard.parse_line(&repair.apply(line))
+    );
+
+    // a stray closer with nothing open can only go away
+    let line = "()]";
+    let result = standard.parse_line(line);
+    assert_eq!(
+        Some(Repair::Delete {
+            at: Position { byte: 2, column: 3 },
+            len: 1
+        }),
+        standard.repair(line, &result)
+    );
+
+    // inserting the missing closer fixes more than swapping the wrong one
+    let line = "(<)";
+    let result = standard.parse_line(line);
+    let repair = standard.repair(line, &result).unwrap();
+    assert_eq!("(<>)", repair.apply(line));
+
+    // columns count characters, bytes don't
+    let quoted = DelimiterSet::default().with_quote("'", "'").unwrap();
+    let line = "('é')]";
+    assert_eq!(
+        Some(Position { byte: 6, column: 6 }),
+        quoted.parse_line(line).position()
+    );
+    let line = "(x)";
+    let result = quoted.parse_line(line);
+    assert_eq!(
+        ParseResult::IllegalCharacter(Position { byte: 1, column: 2 }, 'x'),
+        result
+    );
+    assert_eq!("()", quoted.repair(line, &result).unwrap().apply(line));
+}
+
+#[test]
+fn streaming() {
+    let mut median = RunningMedian::default();
+    assert_eq!(None, median.median());
+    for (value, expected) in [(5, 5), (1, 5), (3, 3), (9, 5), (7, 5), (8, 7)] {
+        median.push(value);
+        assert_eq!(Some(expected), median.median());
+    }
+    assert_eq!(6, median.len());
+
+    let delimiters = DelimiterSet::default();
+    let syntax = SyntaxScoring::default();
+    let completion = CompletionScoring::default();
+    let mut validator = StreamValidator::new(&delimiters, &syntax, &completion);
+    let mut output = Vec::new();
+    validator.run(DAY10_TEST.as_bytes(), &mut output).unwrap();
+    assert_eq!(Some(288957), validator.median_completion());
+    assert_eq!(
+        &Statistics {
+            lines: 10,
+            clean: 0,
+            corrupted: 5,
+            incomplete: 5,
+            illegal: 0,
+            syntax_score: 26397,
+        },
+        validator.statistics()
+    );
+
+    let output = String::from_utf8(output).unwrap();
+    let records: Vec<&str> = output.lines().collect();
+    assert_eq!(10, records.len());
+    assert_eq!(
+        "{\"line\":1,\"status\":\"incomplete\",\"score\":288957,\"repaired\":\"[({(<(())[]>[[{[]{<()<>>}}]])})]\"}",
+        records[0]
+    );
+    assert_eq!(
+        "{\"line\":3,\"status\":\"corrupted\",\"byte\":12,\"column\":13,\"found\":\"}\",\"expected\":\"]\",\"score\":1197,\"repaired\":\"{([(<{}[<>[]]>{[]{[(<()>\"}",
+        records[2]
+    );
+
+    let quoted = DelimiterSet::default().with_quote("\"", "\"").unwrap();
+    let mut validator = StreamValidator::new(&quoted, &syntax, &completion);
+    assert_eq!("{\"line\":1,\"status\":\"ok\"}", validator.push("(\"\\\")"));
+    assert_eq!(
+        "{\"line\":2,\"status\":\"illegal\",\"byte\":1,\"column\":2,\"found\":\"\\t\",\"score\":0,\"repaired\":\"()\"}",
+        validator.push("(\t)")
+    );
+    assert_eq!(None, validator.median_completion());
+}
@@ -0,0 +1,455 @@
+use pathfinding::num_traits::Zero;
+use pathfinding::prelude::{
+    bfs, bfs_loop, dijkstra, strongly_connected_components, topological_sort,
+};
+use std::collections::HashMap;
+use std::str::FromStr;
+use thiserror::Error;
+
+#[cfg(test)]
+const DAY12_TEST1: &str = include_str!("../data/day12t1.txt");
+#[cfg(test)]
+const DAY12_TEST2: &str = include_str!("../data/day12t2.txt");
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Oopsie {
+    #[error("Bad edge line found: {0}")]
+    BadLine(String),
+    #[error("Bad edge weight found: {0}")]
+    BadWeight(String),
+    #[error("Graph has a cycle through {0}")]
+    CycleFound(String),
+}
+
+pub type NodeId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Direction {
+    Directed,
+    Undirected,
+}
+
+#[derive(Clone, Debug)]
+pub struct Graph<W = ()> {
+    direction: Direction,
+    names: Vec<String>,
+    ids: HashMap<String, NodeId>,
+    adjacency: Vec<Vec<(NodeId, W)>>,
+}
+
+impl<W> Graph<W> {
+    pub fn new(direction: Direction) -> Graph<W> {
+        Graph {
+            direction,
+            names: Vec::new(),
+            ids: HashMap::new(),
+            adjacency: Vec::new(),
+        }
+    }
+
+    pub fn direction(&self) -> Direction {
+        self.direction
+    }
+
+    pub fn intern(&mut self, name: &str) -> NodeId {
+        if let Some(id) = self.ids.get(name) {
+            return *id;
+        }
+
+        let id = self.names.len();
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        self.adjacency.push(Vec::new());
+        id
+    }
+
+    pub fn id(&self, name: &str) -> Option<NodeId> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: NodeId) -> &str {
+        &self.names[id]
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.edges().count()
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = NodeId> {
+        0..self.names.len()
+    }
+
+    pub fn add_edge(&mut self, from: &str, to: &str, weight: W)
+    where
+        W: Clone,
+    {
+        let from = self.intern(from);
+        let to = self.intern(to);
+        self.add_edge_ids(from, to, weight);
+    }
+
+    pub fn add_edge_ids(&mut self, from: NodeId, to: NodeId, weight: W)
+    where
+        W: Clone,
+    {
+        if self.direction == Direction::Undirected && from != to {
+            Graph::insert_half_edge(&mut self.adjacency[to], from, weight.clone());
+        }
+        Graph::insert_half_edge(&mut self.adjacency[from], to, weight);
+    }
+
+    fn insert_half_edge(edges: &mut Vec<(NodeId, W)>, to: NodeId, weight: W) {
+        match edges.iter_mut().find(|(x, _)| *x == to) {
+            None => edges.push((to, weight)),
+            Some(existing) => existing.1 = weight,
+        }
+    }
+
+    pub fn has_edge(&self, from: NodeId, to: NodeId) -> bool {
+        self.adjacency[from].iter().any(|(x, _)| *x == to)
+    }
+
+    pub fn weight(&self, from: NodeId, to: NodeId) -> Option<&W> {
+        self.adjacency[from]
+            .iter()
+            .find(|(x, _)| *x == to)
+            .map(|(_, weight)| weight)
+    }
+
+    pub fn degree(&self, id: NodeId) -> usize {
+        self.adjacency[id].len()
+    }
+
+    pub fn in_degree(&self, id: NodeId) -> usize {
+        match self.direction {
+            Direction::Undirected => self.degree(id),
+            Direction::Directed => self
+                .adjacency
+                .iter()
+                .filter(|edges| edges.iter().any(|(x, _)| *x == id))
+                .count(),
+        }
+    }
+
+    pub fn neighbors(&self, id: NodeId) -> impl Iterator<Item = NodeId> + '_ {
+        self.adjacency[id].iter().map(|(x, _)| *x)
+    }
+
+    pub fn weighted_neighbors(&self, id: NodeId) -> impl Iterator<Item = (NodeId, &W)> + '_ {
+        self.adjacency[id].iter().map(|(x, weight)| (*x, weight))
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = (NodeId, NodeId, &W)> + '_ {
+        self.adjacency
+            .iter()
+            .enumerate()
+            .flat_map(|(from, edges)| edges.iter().map(move |(to, weight)| (from, *to, weight)))
+            .filter(|(from, to, _)| self.direction == Direction::Directed || from <= to)
+    }
+}
+
+impl<W> Graph<W> {
+    pub fn strongly_connected_components(&self) -> Vec<Vec<NodeId>> {
+        let nodes: Vec<NodeId> = self.nodes().collect();
+        strongly_connected_components(&nodes, |x| self.neighbors(*x))
+    }
+
+    pub fn topological_sort(&self) -> Result<Vec<NodeId>, Oopsie> {
+        let nodes: Vec<NodeId> = self.nodes().collect();
+        topological_sort(&nodes, |x| self.neighbors(*x))
+            .map_err(|x| Oopsie::CycleFound(self.name(x).to_string()))
+    }
+
+    pub fn shortest_path(&self, from: NodeId, to: NodeId) -> Option<Vec<NodeId>> {
+        bfs(&from, |x| self.neighbors(*x), |x| *x == to)
+    }
+
+    // finds a closed walk that only passes through nodes accepted by
+    // `allowed`; in an undirected graph, walking back and forth along a
+    // single edge counts
+    pub fn find_cycle_within<F: Fn(NodeId) -> bool>(&self, allowed: F) -> Option<Vec<NodeId>> {
+        let allowed = &allowed;
+
+        self.nodes()
+            .filter(|x| allowed(*x))
+            .find_map(|start| bfs_loop(&start, |x| self.neighbors(*x).filter(move |y| allowed(*y))))
+    }
+
+    // directed graphs are treated as their underlying undirected graph
+    pub fn articulation_points(&self) -> Vec<NodeId> {
+        let mut links: Vec<Vec<NodeId>> =
+            self.nodes().map(|x| self.neighbors(x).collect()).collect();
+        if self.direction == Direction::Directed {
+            for (from, to, _) in self.edges() {
+                if !links[to].contains(&from) {
+                    links[to].push(from);
+                }
+            }
+        }
+
+        let mut state = ArticulationState {
+            links,
+            time: 0,
+            discovered: vec![None; self.node_count()],
+            low: vec![0; self.node_count()],
+            points: vec![false; self.node_count()],
+        };
+
+        for root in self.nodes() {
+            if state.discovered[root].is_none() {
+                state.search(root, None);
+            }
+        }
+
+        self.nodes().filter(|x| state.points[*x]).collect()
+    }
+}
+
+struct ArticulationState {
+    links: Vec<Vec<NodeId>>,
+    time: usize,
+    discovered: Vec<Option<usize>>,
+    low: Vec<usize>,
+    points: Vec<bool>,
+}
+
+impl ArticulationState {
+    fn search(&mut self, node: NodeId, parent: Option<NodeId>) {
+        let discovered = self.time;
+        self.discovered[node] = Some(discovered);
+        self.low[node] = discovered;
+        self.time += 1;
+
+        let mut children = 0;
+        for idx in 0..self.links[node].len() {
+            let next = self.links[node][idx];
+
+            match self.discovered[next] {
+                None => {
+                    children += 1;
+                    self.search(next, Some(node));
+                    self.low[node] = self.low[node].min(self.low[next]);
+                    if parent.is_some() && self.low[next] >= discovered {
+                        self.points[node] = true;
+                    }
+                }
+                Some(x) if Some(next) != parent => self.low[node] = self.low[node].min(x),
+                Some(_) => {}
+            }
+        }
+
+        if parent.is_none() && children > 1 {
+            self.points[node] = true;
+        }
+    }
+}
+
+impl<W: Copy + Ord + Zero> Graph<W> {
+    pub fn shortest_weighted_path(&self, from: NodeId, to: NodeId) -> Option<(Vec<NodeId>, W)> {
+        dijkstra(
+            &from,
+            |x| {
+                self.weighted_neighbors(*x)
+                    .map(|(next, weight)| (next, *weight))
+            },
+            |x| *x == to,
+        )
+    }
+}
+
+impl Graph<()> {
+    pub fn from_edge_list(data: &str, direction: Direction) -> Result<Graph<()>, Oopsie> {
+        let mut graph = Graph::new(direction);
+
+        for line in data.lines() {
+            let (from, to) = line
+                .split_once('-')
+                .ok_or_else(|| Oopsie::BadLine(line.to_string()))?;
+            graph.add_edge(from.trim(), to.trim(), ());
+        }
+
+        Ok(graph)
+    }
+}
+
+impl<W: Clone + FromStr> Graph<W> {
+    pub fn from_weighted_edge_list(data: &str, direction: Direction) -> Result<Graph<W>, Oopsie> {
+        let mut graph = Graph::new(direction);
+
+        for line in data.lines() {
+            let (edge, weight) = line
+                .split_once('=')
+                .ok_or_else(|| Oopsie::BadLine(line.to_string()))?;
+            let (from, to) = edge
+                .split_once('-')
+                .ok_or_else(|| Oopsie::BadLine(line.to_string()))?;
+            let weight = W::from_str(weight.trim())
+                .map_err(|_| Oopsie::BadWeight(weight.trim().to_string()))?;
+            graph.add_edge(from.trim(), to.trim(), weight);
+        }
+
+        Ok(graph)
+    }
+}
+
+#[test]
+fn edge_lists() {
+    let caves = Graph::from_edge_list(DAY12_TEST1, Direction::Undirected).unwrap();
+    let start = caves.id("start").unwrap();
+    let big = caves.id("A").unwrap();
+
+    assert_eq!(6, caves.node_count());
+    assert_eq!(7, caves.edge_count());
+    assert_eq!("A", caves.name(big));
+    assert_eq!(4, caves.degree(big));
+    assert_eq!(2, caves.in_degree(start));
+    let mut neighbors: Vec<&str> = caves.neighbors(start).map(|x| caves.name(x)).collect();
+    neighbors.sort_unstable();
+    assert_eq!(vec!["A", "b"], neighbors);
+
+    let directed = Graph::from_edge_list(DAY12_TEST1, Direction::Directed).unwrap();
+    assert_eq!(7, directed.edge_count());
+    assert_eq!(2, directed.degree(start));
+    assert_eq!(0, directed.in_degree(start));
+    assert!(!directed.has_edge(big, start));
+
+    let routes: Graph<u32> = Graph::from_weighted_edge_list(
+        "London-Dublin = 464\nLondon-Belfast = 518\nDublin-Belfast = 141",
+        Direction::Undirected,
+    )
+    .unwrap();
+    let dublin = routes.id("Dublin").unwrap();
+    let belfast = routes.id("Belfast").unwrap();
+    assert_eq!(Some(&141), routes.weight(belfast, dublin));
+    assert_eq!(1123u32, routes.edges().map(|(_, _, x)| *x).sum());
+
+    assert_eq!(
+        Err(Oopsie::BadLine("start".to_string())),
+        Graph::from_edge_list("start", Direction::Undirected).map(|x| x.node_count())
+    );
+    assert_eq!(
+        Err(Oopsie::BadWeight("far".to_string())),
+        Graph::<u32>::from_weighted_edge_list("a-b=far", Direction::Directed)
+            .map(|x| x.node_count())
+    );
+}
+
+#[cfg(test)]
+fn names(graph: &Graph<impl Sized>, ids: &[NodeId]) -> Vec<String> {
+    let mut result: Vec<String> = ids.iter().map(|x| graph.name(*x).to_string()).collect();
+    result.sort_unstable();
+    result
+}
+
+#[test]
+fn components_and_ordering() {
+    let caves = Graph::from_edge_list(DAY12_TEST1, Direction::Undirected).unwrap();
+    let components = caves.strongly_connected_components();
+    assert_eq!(1, components.len());
+    assert_eq!(6, components[0].len());
+    assert!(matches!(
+        caves.topological_sort(),
+        Err(Oopsie::CycleFound(_))
+    ));
+
+    let directed = Graph::from_edge_list(DAY12_TEST1, Direction::Directed).unwrap();
+    assert_eq!(6, directed.strongly_connected_components().len());
+    let order = directed.topological_sort().unwrap();
+    assert_eq!("start", directed.name(order[0]));
+    for (from, to, _) in directed.edges() {
+        let from_idx = order.iter().position(|x| *x == from).unwrap();
+        let to_idx = order.iter().position(|x| *x == to).unwrap();
+        assert!(from_idx < to_idx);
+    }
+
+    let mut looping = Graph::from_edge_list(DAY12_TEST2, Direction::Directed).unwrap();
+    looping.add_edge("end", "HN", ());
+    let components = looping.strongly_connected_components();
+    let cycle = components.iter().find(|x| x.len() > 1).unwrap();
+    assert_eq!(
+        vec!["HN", "dc", "end", "kj", "start"],
+        names(&looping, cycle)
+    );
+    assert!(matches!(
+        looping.topological_sort(),
+        Err(Oopsie::CycleFound(_))
+    ));
+}
+
+#[test]
+fn shortest_paths() {
+    let caves = Graph::from_edge_list(DAY12_TEST1, Direction::Undirected).unwrap();
+    let start = caves.id("start").unwrap();
+    let end = caves.id("end").unwrap();
+    let d = caves.id("d").unwrap();
+    assert_eq!(3, caves.shortest_path(start, end).unwrap().len());
+    assert_eq!(
+        vec!["b", "d", "start"],
+        names(&caves, &caves.shortest_path(start, d).unwrap())
+    );
+
+    let weighted_data: String = DAY12_TEST1
+        .lines()
+        .map(|line| {
+            let cost = if line.chars().any(|x| x.is_uppercase()) {
+                1
+            } else {
+                5
+            };
+            format!("{}={}\n", line, cost)
+        })
+        .collect();
+    let weighted: Graph<u32> =
+        Graph::from_weighted_edge_list(&weighted_data, Direction::Undirected).unwrap();
+    let start = weighted.id("start").unwrap();
+    let (path, cost) = weighted
+        .shortest_weighted_path(start, weighted.id("d").unwrap())
+        .unwrap();
+    assert_eq!(7, cost);
+    assert_eq!(
+        vec!["start", "A", "b", "d"],
+        path.iter().map(|x| weighted.name(*x)).collect::<Vec<_>>()
+    );
+    assert_eq!(
+        Some(2),
+        weighted
+            .shortest_weighted_path(start, weighted.id("end").unwrap())
+            .map(|(_, cost)| cost)
+    );
+
+    let mut disconnected = Graph::from_edge_list(DAY12_TEST1, Direction::Directed).unwrap();
+    let island = disconnected.intern("island");
+    assert_eq!(None, disconnected.shortest_path(start, island));
+}
+
+#[test]
+fn cycles_and_articulation_points() {
+    let is_big = |graph: &Graph, x: NodeId| graph.name(x).chars().all(|c| c.is_uppercase());
+
+    let test1 = Graph::from_edge_list(DAY12_TEST1, Direction::Undirected).unwrap();
+    let test2 = Graph::from_edge_list(DAY12_TEST2, Direction::Undirected).unwrap();
+    assert_eq!(None, test1.find_cycle_within(|x| is_big(&test1, x)));
+    assert_eq!(None, test2.find_cycle_within(|x| is_big(&test2, x)));
+
+    let mut looping = Graph::from_edge_list(DAY12_TEST2, Direction::Undirected).unwrap();
+    looping.add_edge("HN", "LN", ());
+    let cycle = looping.find_cycle_within(|x| is_big(&looping, x)).unwrap();
+    assert_eq!(3, cycle.len());
+    assert_eq!(cycle[0], cycle[2]);
+    assert_eq!(vec!["HN", "LN"], names(&looping, &cycle[..2]));
+
+    assert_eq!(vec!["A", "b"], names(&test1, &test1.articulation_points()));
+    assert_eq!(
+        vec!["dc", "kj"],
+        names(&test2, &test2.articulation_points())
+    );
+    let directed = Graph::from_edge_list(DAY12_TEST2, Direction::Directed).unwrap();
+    assert_eq!(
+        vec!["dc", "kj"],
+        names(&directed, &directed.articulation_points())
+    );
+}
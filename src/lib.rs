@@ -1,4 +1,8 @@
+pub mod delimiters;
+pub mod graph;
 pub mod map;
+pub mod prng;
+pub mod submarine;
 
 use std::str::FromStr;
 
@@ -70,6 +70,14 @@ impl<T: TryFrom<char, Error = Oopsie>> Graph<T> {
 }
 
 impl<T: Clone> Graph<T> {
+    pub fn new(width: usize, height: usize, value: T) -> Graph<T> {
+        Graph {
+            data: vec![value; width * height],
+            width,
+            height,
+        }
+    }
+
     pub fn from_subgraphs(num_wide: usize, num_high: usize, graphs: &[Graph<T>]) -> Graph<T> {
         let inner_width = graphs[0].width;
         let inner_height = graphs[0].height;
@@ -107,7 +115,7 @@ impl<T> Graph<T> {
         self.width * self.height
     }
 
-    pub fn get(&self, x: usize, y: usize) -> Option<Point<T>> {
+    pub fn get(&self, x: usize, y: usize) -> Option<Point<'_, T>> {
         if x >= self.width || y >= self.height {
             return None;
         }
@@ -119,7 +127,7 @@ impl<T> Graph<T> {
         })
     }
 
-    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<PointMut<T>> {
+    pub fn get_mut(&mut self, x: usize, y: usize) -> Option<PointMut<'_, T>> {
         if x >= self.width || y >= self.height {
             return None;
         }
@@ -131,7 +139,7 @@ impl<T> Graph<T> {
         })
     }
 
-    pub fn coordinates(&self) -> Coord<T> {
+    pub fn coordinates(&self) -> Coord<'_, T> {
         Coord {
             graph: self,
             curx: 0,
@@ -147,7 +155,7 @@ impl<T> Graph<T> {
         }
     }
 
-    pub fn points_mut(&mut self) -> impl Iterator<Item = PointMut<T>> {
+    pub fn points_mut(&mut self) -> impl Iterator<Item = PointMut<'_, T>> {
         let coords: Vec<(usize, usize)> = self.coordinates().collect();
         self.data
             .iter_mut()
@@ -155,7 +163,7 @@ impl<T> Graph<T> {
             .map(|(value, (x, y))| PointMut { x, y, value })
     }
 
-    pub fn neighbors(&self, x: usize, y: usize) -> Vec<Point<T>> {
+    pub fn neighbors(&self, x: usize, y: usize) -> Vec<Point<'_, T>> {
         let mut retval = Vec::new();
 
         if x > 0 {
@@ -0,0 +1,458 @@
+use std::fmt;
+use std::num;
+use std::ops::Range;
+use std::str::FromStr;
+use thiserror::Error;
+
+use crate::from_file_data;
+
+const DAY2_TEST: &str = include_str!("../data/day2_test.txt");
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Oopsie {
+    #[error("Couldn't understand '{0}' as a command.")]
+    CouldntParseCommand(String),
+    #[error("Couldn't parse number: {0}")]
+    CouldntParseNumber(#[from] num::ParseIntError),
+    #[error("Expected {0} results, one per fixture, but got {1}")]
+    WrongResultCount(usize, usize),
+    #[error("Fixture '{fixture}' under {interpretation} ended at {found:?}, not {expected:?}")]
+    WrongPosition {
+        fixture: &'static str,
+        interpretation: String,
+        expected: (isize, isize),
+        found: (isize, isize),
+    },
+    #[error("Tracing changed how fixture '{0}' turned out")]
+    TraceMismatch(&'static str),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Command {
+    Forward(isize),
+    Down(isize),
+    Up(isize),
+    Back(isize),
+    Turn,
+    SetAim(isize),
+}
+
+impl FromStr for Command {
+    type Err = Oopsie;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let words: Vec<&str> = s.split_whitespace().collect();
+
+        match words[..] {
+            ["forward", v] => Ok(Command::Forward(isize::from_str(v)?)),
+            ["down", v] => Ok(Command::Down(isize::from_str(v)?)),
+            ["up", v] => Ok(Command::Up(isize::from_str(v)?)),
+            ["back", v] => Ok(Command::Back(isize::from_str(v)?)),
+            ["turn"] => Ok(Command::Turn),
+            ["set", "aim", v] => Ok(Command::SetAim(isize::from_str(v)?)),
+            _ => Err(Oopsie::CouldntParseCommand(s.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Command::Forward(v) => write!(f, "forward {}", v),
+            Command::Down(v) => write!(f, "down {}", v),
+            Command::Up(v) => write!(f, "up {}", v),
+            Command::Back(v) => write!(f, "back {}", v),
+            Command::Turn => write!(f, "turn"),
+            Command::SetAim(v) => write!(f, "set aim {}", v),
+        }
+    }
+}
+
+#[test]
+fn command_parsing() {
+    assert_eq!(Ok(Command::Forward(5)), Command::from_str("forward 5"));
+    assert_eq!(Ok(Command::Down(5)), Command::from_str("down 5"));
+    assert_eq!(Ok(Command::Forward(8)), Command::from_str("forward 8"));
+    assert_eq!(Ok(Command::Up(3)), Command::from_str("up 3"));
+    assert_eq!(Ok(Command::Down(8)), Command::from_str("down 8"));
+    assert_eq!(Ok(Command::Forward(2)), Command::from_str("forward 2"));
+    assert_eq!(Ok(Command::Back(4)), Command::from_str("back 4"));
+    assert_eq!(Ok(Command::Turn), Command::from_str("turn"));
+    assert_eq!(Ok(Command::SetAim(-3)), Command::from_str("set  aim -3"));
+    assert!(Command::from_str("sideways 3").is_err());
+}
+
+#[test]
+fn file_parsing() {
+    let result: Result<Vec<Command>, Oopsie> = from_file_data(DAY2_TEST);
+    assert!(result.is_ok());
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct State {
+    pub position_x: isize,
+    pub depth: isize,
+    pub aim: isize,
+    // 1 when forward means +x, -1 after an odd number of turns
+    pub heading: isize,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            position_x: 0,
+            depth: 0,
+            aim: 0,
+            heading: 1,
+        }
+    }
+}
+
+// steps are numbered from 1, with step 0 being where the sub started
+#[derive(Debug, Default)]
+pub struct Trace {
+    pub start: State,
+    pub steps: Vec<(Command, State)>,
+}
+
+impl Trace {
+    pub fn states(&self) -> impl Iterator<Item = &State> {
+        std::iter::once(&self.start).chain(self.steps.iter().map(|(_, state)| state))
+    }
+
+    pub fn max_depth(&self) -> (usize, isize) {
+        let mut best = (0, self.start.depth);
+
+        for (step, state) in self.states().enumerate() {
+            if state.depth > best.1 {
+                best = (step, state.depth);
+            }
+        }
+
+        best
+    }
+
+    // the first command that takes the sub from above the threshold to at or below it
+    pub fn first_crossing(&self, threshold: isize) -> Option<(usize, Command)> {
+        let mut previous = self.start.depth;
+
+        for (idx, (command, state)) in self.steps.iter().enumerate() {
+            if previous < threshold && state.depth >= threshold {
+                return Some((idx + 1, *command));
+            }
+            previous = state.depth;
+        }
+
+        None
+    }
+
+    // runs of steps that left the sub with a negative depth
+    pub fn above_surface(&self) -> Vec<Range<usize>> {
+        let mut result: Vec<Range<usize>> = Vec::new();
+
+        for (step, state) in self.states().enumerate() {
+            if state.depth >= 0 {
+                continue;
+            }
+            match result.last_mut() {
+                Some(run) if run.end == step => run.end = step + 1,
+                _ => result.push(step..step + 1),
+            }
+        }
+
+        result
+    }
+
+    pub fn to_csv(&self) -> String {
+        let mut result = String::from("step,command,x,depth,aim\n");
+        let row = |step: usize, command: &str, state: &State| {
+            format!(
+                "{},{},{},{},{}\n",
+                step, command, state.position_x, state.depth, state.aim
+            )
+        };
+
+        result.push_str(&row(0, "start", &self.start));
+        for (idx, (command, state)) in self.steps.iter().enumerate() {
+            result.push_str(&row(idx + 1, &command.to_string(), state));
+        }
+
+        result
+    }
+
+    // (min x, min depth, max x, max depth), always including the surface
+    pub fn bounds(&self) -> (isize, isize, isize, isize) {
+        let mut result = (self.start.position_x, 0, self.start.position_x, 0);
+
+        for state in self.states() {
+            result.0 = result.0.min(state.position_x);
+            result.1 = result.1.min(state.depth);
+            result.2 = result.2.max(state.position_x);
+            result.3 = result.3.max(state.depth);
+        }
+
+        result
+    }
+
+    // x runs left to right and depth runs top to bottom; ~ marks the surface
+    pub fn ascii_plot(&self, width: usize, height: usize) -> String {
+        if width == 0 || height == 0 {
+            return String::new();
+        }
+
+        let (min_x, min_depth, max_x, max_depth) = self.bounds();
+        let scale = |value: isize, low: isize, high: isize, cells: usize| -> isize {
+            if high == low || cells < 2 {
+                0
+            } else {
+                ((value - low) as i128 * (cells as i128 - 1) / (high - low) as i128) as isize
+            }
+        };
+        let cell = |state: &State| {
+            (
+                scale(state.position_x, min_x, max_x, width),
+                scale(state.depth, min_depth, max_depth, height),
+            )
+        };
+        let mut grid = vec![vec![' '; width]; height];
+
+        let surface = scale(0, min_depth, max_depth, height) as usize;
+        grid[surface].iter_mut().for_each(|c| *c = '~');
+
+        let cells: Vec<(isize, isize)> = self.states().map(cell).collect();
+        for pair in cells.windows(2) {
+            let ((x0, y0), (x1, y1)) = (pair[0], pair[1]);
+            let samples = (x1 - x0).abs().max((y1 - y0).abs()).max(1);
+            for i in 0..=samples {
+                let x = x0 + (x1 - x0) * i / samples;
+                let y = y0 + (y1 - y0) * i / samples;
+                grid[y as usize][x as usize] = '*';
+            }
+        }
+        let (x, y) = cells[0];
+        grid[y as usize][x as usize] = 'S';
+        let (x, y) = cells[cells.len() - 1];
+        grid[y as usize][x as usize] = 'E';
+
+        let mut result = String::new();
+        for row in grid.iter() {
+            let line: String = row.iter().collect();
+            result.push_str(line.trim_end());
+            result.push('\n');
+        }
+        result
+    }
+
+    pub fn svg(&self, width: usize, height: usize) -> String {
+        let (min_x, min_depth, max_x, max_depth) = self.bounds();
+        let points: Vec<String> = self
+            .states()
+            .map(|state| format!("{},{}", state.position_x, state.depth))
+            .collect();
+
+        format!(
+            concat!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" ",
+                "viewBox=\"{} {} {} {}\" preserveAspectRatio=\"none\">\n",
+                "  <line x1=\"{}\" y1=\"0\" x2=\"{}\" y2=\"0\" stroke=\"steelblue\" ",
+                "vector-effect=\"non-scaling-stroke\"/>\n",
+                "  <polyline points=\"{}\" fill=\"none\" stroke=\"black\" ",
+                "vector-effect=\"non-scaling-stroke\"/>\n",
+                "</svg>\n"
+            ),
+            width,
+            height,
+            min_x,
+            min_depth,
+            (max_x - min_x).max(1),
+            (max_depth - min_depth).max(1),
+            min_x,
+            max_x,
+            points.join(" ")
+        )
+    }
+}
+
+// how a submarine reacts to each command; implement this to try out new physics
+pub trait Interpretation: fmt::Debug {
+    fn apply(&self, state: &mut State, cmd: &Command);
+}
+
+#[derive(Debug, Default)]
+pub struct Basic;
+
+impl Interpretation for Basic {
+    fn apply(&self, state: &mut State, cmd: &Command) {
+        match cmd {
+            Command::Forward(v) => state.position_x += state.heading * v,
+            Command::Back(v) => state.position_x -= state.heading * v,
+            Command::Up(v) => state.depth -= v,
+            Command::Down(v) => state.depth += v,
+            Command::Turn => state.heading = -state.heading,
+            Command::SetAim(v) => state.aim = *v,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct WithAim;
+
+impl Interpretation for WithAim {
+    fn apply(&self, state: &mut State, cmd: &Command) {
+        match cmd {
+            Command::Forward(v) => {
+                state.position_x += state.heading * v;
+                state.depth += state.aim * v;
+            }
+            Command::Back(v) => {
+                state.position_x -= state.heading * v;
+                state.depth -= state.aim * v;
+            }
+            Command::Up(v) => state.aim -= v,
+            Command::Down(v) => state.aim += v,
+            Command::Turn => state.heading = -state.heading,
+            Command::SetAim(v) => state.aim = *v,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Submarine {
+    pub interpretation: Box<dyn Interpretation>,
+    pub state: State,
+    pub trace: Option<Trace>,
+}
+
+impl Submarine {
+    pub fn new<I: Interpretation + 'static>(interpretation: I) -> Submarine {
+        Submarine {
+            interpretation: Box::new(interpretation),
+            state: State::default(),
+            trace: None,
+        }
+    }
+
+    pub fn with_trace(mut self) -> Submarine {
+        self.trace = Some(Trace {
+            start: self.state,
+            steps: Vec::new(),
+        });
+        self
+    }
+
+    pub fn command(&mut self, cmd: &Command) {
+        self.interpretation.apply(&mut self.state, cmd);
+
+        if let Some(trace) = self.trace.as_mut() {
+            trace.steps.push((*cmd, self.state));
+        }
+    }
+
+    pub fn run(&mut self, cmds: &[Command]) {
+        for cmd in cmds.iter() {
+            self.command(cmd);
+        }
+    }
+
+    pub fn distance(&self) -> isize {
+        self.state.depth * self.state.position_x
+    }
+}
+
+// every scenario here runs through every interpretation; each one supplies
+// where it expects the sub to end up, as (position_x, depth)
+pub const FIXTURES: [(&str, &str); 4] = [
+    ("example", DAY2_TEST),
+    ("turnaround", "forward 4\ndown 2\nturn\nforward 1\nback 3\n"),
+    ("resurface", "down 3\nforward 2\nup 5\nforward 2\n"),
+    ("aimed", "set aim 2\nforward 3\nset aim -1\nforward 1\n"),
+];
+
+pub fn check_interpretation<I, F>(make: F, expected: &[(isize, isize)]) -> Result<(), Oopsie>
+where
+    I: Interpretation + 'static,
+    F: Fn() -> I,
+{
+    if FIXTURES.len() != expected.len() {
+        return Err(Oopsie::WrongResultCount(FIXTURES.len(), expected.len()));
+    }
+
+    for ((name, text), position) in FIXTURES.iter().zip(expected.iter()) {
+        let commands: Vec<Command> = from_file_data(text)?;
+        let mut plain = Submarine::new(make());
+        plain.run(&commands);
+        let mut traced = Submarine::new(make()).with_trace();
+        traced.run(&commands);
+
+        let found = (plain.state.position_x, plain.state.depth);
+        if found != *position {
+            return Err(Oopsie::WrongPosition {
+                fixture: name,
+                interpretation: format!("{:?}", plain.interpretation),
+                expected: *position,
+                found,
+            });
+        }
+
+        // tracing should never change where the sub ends up
+        let trace = traced.trace.as_ref().unwrap();
+        if plain.state != traced.state
+            || commands.len() != trace.steps.len()
+            || Some(&plain.state) != trace.states().last()
+        {
+            return Err(Oopsie::TraceMismatch(name));
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn builtin_interpretations() {
+    assert_eq!(
+        Ok(()),
+        check_interpretation(|| Basic, &[(15, 10), (6, 2), (4, -2), (4, 0)])
+    );
+    assert_eq!(
+        Ok(()),
+        check_interpretation(|| WithAim, &[(15, 60), (6, -4), (4, 2), (4, 5)])
+    );
+    assert_eq!(
+        Err(Oopsie::WrongResultCount(4, 1)),
+        check_interpretation(|| Basic, &[(15, 10)])
+    );
+    assert_eq!(
+        Err(Oopsie::WrongPosition {
+            fixture: "turnaround",
+            interpretation: "Basic".to_string(),
+            expected: (6, 3),
+            found: (6, 2),
+        }),
+        check_interpretation(|| Basic, &[(15, 10), (6, 3), (4, -2), (4, 0)])
+    );
+}
+
+#[test]
+fn custom_interpretation() {
+    // a current that pushes the sub one unit backwards every time it moves
+    #[derive(Debug)]
+    struct Current(isize);
+
+    impl Interpretation for Current {
+        fn apply(&self, state: &mut State, cmd: &Command) {
+            WithAim.apply(state, cmd);
+            if let Command::Forward(_) | Command::Back(_) = cmd {
+                state.position_x -= self.0;
+            }
+        }
+    }
+
+    assert_eq!(
+        Ok(()),
+        check_interpretation(|| Current(0), &[(15, 60), (6, -4), (4, 2), (4, 5)])
+    );
+    assert_eq!(
+        Ok(()),
+        check_interpretation(|| Current(1), &[(12, 60), (3, -4), (2, 2), (2, 5)])
+    );
+}